@@ -0,0 +1,1204 @@
+//! A parser for a simple subset of CSS.
+//!
+//! Stylesheets are split into tokens by a [`Tokenizer`] that follows CSS Syntax Level 3, and
+//! rules and declarations are then read from the tokens.
+
+use error::ParseError;
+
+pub use self::tokenizer::{Token, Tokenizer};
+
+mod tokenizer;
+
+/// A parsed stylesheet: an ordered list of rules.
+pub struct StyleSheet {
+    pub rules: Vec<Rule>,
+    pub origin: Origin,
+}
+
+/// Where a stylesheet comes from, which decides its precedence in the cascade.
+///
+/// Normal declarations from later origins win over earlier ones, and `!important` declarations
+/// reverse that order and win over all normal ones.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Origin {
+    /// The browser's default styles.
+    UserAgent,
+    /// Styles chosen by the reader.
+    User,
+    /// Styles that come with the document.
+    Author,
+}
+
+/// A set of selectors and the declarations that apply to elements they match.
+pub struct Rule {
+    pub selectors: Vec<Selector>,
+    pub declarations: Vec<Declaration>,
+}
+
+pub enum Selector {
+    Simple(SimpleSelector),
+    /// A selector that matches elements matching the simple selector on the right which are
+    /// related by the combinator to an element matching the selector on the left, such as
+    /// `ul > li`.
+    Complex(Box<Selector>, Combinator, SimpleSelector),
+}
+
+/// How the elements matched by the parts of a complex selector are related.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Combinator {
+    /// Whitespace: the left element is an ancestor of the right one.
+    Descendant,
+    /// `>`: the left element is the parent of the right one.
+    Child,
+    /// `+`: the left element immediately precedes the right one.
+    NextSibling,
+    /// `~`: the left element precedes the right one among its siblings.
+    SubsequentSibling,
+}
+
+pub struct SimpleSelector {
+    pub tag_name: Option<String>,
+    pub id: Option<String>,
+    pub class: Vec<String>,
+    pub attributes: Vec<AttributeSelector>,
+    pub pseudo_classes: Vec<PseudoClass>,
+    /// Only allowed in the last simple selector of a selector.
+    pub pseudo_element: Option<PseudoElement>,
+}
+
+/// A pseudo-element that generates a box of its own, inside its originating element.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PseudoElement {
+    /// `::before`: generated content before the element's children.
+    Before,
+    /// `::after`: generated content after the element's children.
+    After,
+}
+
+/// A test on an element's attribute, such as `[type=checkbox]`.
+pub struct AttributeSelector {
+    pub name: String,
+    pub operator: AttributeOperator,
+    /// The value to compare with. Empty for [`AttributeOperator::Exists`].
+    pub value: String,
+    pub case_sensitivity: CaseSensitivity,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AttributeOperator {
+    /// `[attr]`
+    Exists,
+    /// `[attr=value]`
+    Equals,
+    /// `[attr~=value]`: one of the whitespace-separated words is `value`.
+    Includes,
+    /// `[attr|=value]`: `value`, or `value` followed by `-`.
+    DashMatch,
+    /// `[attr^=value]`
+    Prefix,
+    /// `[attr$=value]`
+    Suffix,
+    /// `[attr*=value]`
+    Substring,
+}
+
+/// How an attribute selector compares values, as set by its `i` or `s` flag.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaseSensitivity {
+    /// No flag: case-sensitive, except for the HTML attributes whose values are defined to be
+    /// case-insensitive, such as `type`.
+    Default,
+    /// `s`
+    Sensitive,
+    /// `i`: compare ignoring ASCII case.
+    Insensitive,
+}
+
+/// A pseudo-class such as `:first-child` or `:not(.hidden)`.
+pub enum PseudoClass {
+    /// `:root`: the document element.
+    Root,
+    /// `:empty`: an element without child elements or text.
+    Empty,
+    FirstChild,
+    LastChild,
+    OnlyChild,
+    FirstOfType,
+    LastOfType,
+    OnlyOfType,
+    /// `:nth-child(An+B of S)`. The selectors are empty if there is no `of` clause.
+    NthChild(Nth, Vec<Selector>),
+    /// `:nth-last-child(An+B of S)`
+    NthLastChild(Nth, Vec<Selector>),
+    NthOfType(Nth),
+    NthLastOfType(Nth),
+    /// `:not(S)`: elements that match none of the selectors.
+    Not(Vec<Selector>),
+    /// `:is(S)`: elements that match any of the selectors.
+    Is(Vec<Selector>),
+    /// `:where(S)`: like `:is`, but with no specificity.
+    Where(Vec<Selector>),
+    /// `:has(S)`: elements that some other element relates to as the selectors describe.
+    Has(Vec<RelativeSelector>),
+}
+
+/// The `An+B` argument of the `:nth-*` pseudo-classes, which matches the 1-based positions
+/// `A*n + B` for all n >= 0.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Nth {
+    pub a: i32,
+    pub b: i32,
+}
+
+/// A selector in `:has()`, which starts with a combinator relating it to the element that has
+/// the pseudo-class, such as `> img`. Without an explicit combinator it is a descendant.
+pub struct RelativeSelector {
+    pub combinator: Combinator,
+    pub selector: Selector,
+}
+
+/// A single `name: value` pair inside a rule.
+pub struct Declaration {
+    pub name: String,
+    pub value: Value,
+    /// Whether the declaration was marked `!important`.
+    pub important: bool,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum Value {
+    Keyword(String),
+    Length(f32, Unit),
+    ColorValue(Color),
+    /// The value of the `content` property, unless it is `none` or `normal`.
+    Content(Vec<ContentItem>),
+    /// Counter names and integers, as given to `counter-reset` and `counter-increment`.
+    Counters(Vec<(String, i32)>),
+}
+
+/// A piece of generated content, as listed in the `content` property.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ContentItem {
+    String(String),
+    /// `attr(name)`: the value of an attribute of the originating element, or nothing.
+    Attr(String),
+    /// `counter(name, style)`: the innermost value of a counter, formatted in a
+    /// `list-style-type` such as `decimal` or `upper-roman`.
+    Counter(String, String),
+    /// `counters(name, separator, style)`: every value of a counter in scope, outermost first.
+    Counters(String, String, String),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Unit {
+    Px,
+}
+
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+/// Selector specificity as (id, class, tag) counts, compared lexicographically.
+pub type Specificity = (usize, usize, usize);
+
+impl Selector {
+    /// The specificity of the selector, summed over every simple selector in it.
+    pub fn specificity(&self) -> Specificity {
+        match *self {
+            Selector::Simple(ref simple) => simple.specificity(),
+            Selector::Complex(ref left, _, ref simple) => {
+                let (a, b, c) = left.specificity();
+                let (x, y, z) = simple.specificity();
+                (a + x, b + y, c + z)
+            }
+        }
+    }
+}
+
+impl Selector {
+    /// The pseudo-element of the selector's last simple selector, if any.
+    pub fn pseudo_element(&self) -> Option<PseudoElement> {
+        match *self {
+            Selector::Simple(ref simple) | Selector::Complex(_, _, ref simple) => {
+                simple.pseudo_element
+            }
+        }
+    }
+}
+
+impl SimpleSelector {
+    pub fn specificity(&self) -> Specificity {
+        let a = self.id.iter().count();
+        let b = self.class.len() + self.attributes.len();
+        let c = self.tag_name.iter().count() + self.pseudo_element.iter().count();
+        self.pseudo_classes.iter().fold((a, b, c), |(a, b, c), pseudo_class| {
+            let (x, y, z) = pseudo_class.specificity();
+            (a + x, b + y, c + z)
+        })
+    }
+}
+
+impl PseudoClass {
+    /// Like a class, except that `:not`, `:is` and `:has` count as their most specific argument
+    /// and `:where` counts as nothing.
+    pub fn specificity(&self) -> Specificity {
+        fn max_of<'a, I: Iterator<Item = &'a Selector>>(selectors: I) -> Specificity {
+            selectors.map(Selector::specificity).max().unwrap_or((0, 0, 0))
+        }
+        match *self {
+            PseudoClass::Not(ref selectors) | PseudoClass::Is(ref selectors) => {
+                max_of(selectors.iter())
+            }
+            PseudoClass::Has(ref selectors) => max_of(selectors.iter().map(|r| &r.selector)),
+            PseudoClass::Where(_) => (0, 0, 0),
+            PseudoClass::NthChild(_, ref of) | PseudoClass::NthLastChild(_, ref of) => {
+                let (a, b, c) = max_of(of.iter());
+                (a, b + 1, c)
+            }
+            _ => (0, 1, 0),
+        }
+    }
+}
+
+impl Nth {
+    /// Whether the 1-based `position` is matched.
+    pub fn matches(&self, position: i32) -> bool {
+        if self.a == 0 {
+            position == self.b
+        } else {
+            // Widened so that extreme values of `b` cannot overflow.
+            let n = i64::from(position) - i64::from(self.b);
+            let a = i64::from(self.a);
+            n % a == 0 && n / a >= 0
+        }
+    }
+}
+
+impl Value {
+    /// Convert a length to pixels. Anything that is not a length is treated as zero.
+    pub fn to_px(&self) -> f32 {
+        match *self {
+            Value::Length(f, Unit::Px) => f,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Parse a whole CSS stylesheet, skipping anything invalid. The stylesheet is given the
+/// author origin.
+pub fn parse(source: String) -> StyleSheet {
+    parse_with_warnings(source).0
+}
+
+/// Parse a whole CSS stylesheet, also returning a warning for each part that was skipped.
+///
+/// Errors are recovered from the way browsers do: an invalid declaration is dropped up to the
+/// next `;`, and a rule with an invalid selector is dropped up to its matching `}`. Unsupported
+/// at-rules are skipped without a warning.
+pub fn parse_with_warnings(source: String) -> (StyleSheet, Vec<ParseError>) {
+    let mut parser = Parser::new(tokenizer::preprocess(source));
+    let rules = parser.parse_rules();
+    (StyleSheet { rules, origin: Origin::Author }, parser.warnings)
+}
+
+/// Parse a list of declarations without a surrounding rule, as found in a `style` attribute,
+/// skipping invalid ones.
+pub fn parse_declarations(source: String) -> Vec<Declaration> {
+    Parser::new(tokenizer::preprocess(source)).parse_declarations()
+}
+
+impl StyleSheet {
+    /// A stylesheet without any rules.
+    pub fn empty(origin: Origin) -> StyleSheet {
+        StyleSheet {
+            rules: Vec::new(),
+            origin,
+        }
+    }
+}
+
+struct Parser {
+    input: String,
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// The index at which the tokens currently being parsed end.
+    end: usize,
+    warnings: Vec<ParseError>,
+}
+
+impl Parser {
+    fn new(input: String) -> Parser {
+        let tokens: Vec<(usize, Token)> = Tokenizer::new(&input).collect();
+        Parser {
+            end: tokens.len(),
+            tokens,
+            input,
+            pos: 0,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn parse_rules(&mut self) -> Vec<Rule> {
+        let mut rules = Vec::new();
+        loop {
+            match self.peek() {
+                None => break,
+                Some(&Token::Whitespace) | Some(&Token::Cdo) | Some(&Token::Cdc) => {
+                    self.next();
+                }
+                // At-rules are not supported, and are skipped.
+                Some(&Token::AtKeyword(_)) => self.skip_at_rule(),
+                Some(_) => rules.extend(self.parse_rule()),
+            }
+        }
+        rules
+    }
+
+    fn skip_at_rule(&mut self) {
+        self.next();
+        while let Some(token) = self.next() {
+            match token {
+                Token::Semicolon => break,
+                Token::OpenCurly => {
+                    self.skip_block(Token::CloseCurly);
+                    break;
+                }
+                token => self.skip_nested(&token),
+            }
+        }
+    }
+
+    /// If `token` opens a block or function, skip its contents.
+    fn skip_nested(&mut self, token: &Token) {
+        match *token {
+            Token::OpenCurly => self.skip_block(Token::CloseCurly),
+            Token::OpenSquare => self.skip_block(Token::CloseSquare),
+            Token::OpenParen | Token::Function(_) => self.skip_block(Token::CloseParen),
+            _ => {}
+        }
+    }
+
+    /// Skip to just past the `end` token that closes the current block.
+    fn skip_block(&mut self, end: Token) {
+        while let Some(token) = self.next() {
+            if token == end {
+                break;
+            }
+            self.skip_nested(&token);
+        }
+    }
+
+    /// Parse a rule, or skip it and record a warning if its selector is invalid.
+    fn parse_rule(&mut self) -> Option<Rule> {
+        let prelude_start = self.pos;
+        loop {
+            match self.next() {
+                Some(Token::OpenCurly) => break,
+                Some(token) => self.skip_nested(&token),
+                None => {
+                    self.warnings.push(self.error("'{'"));
+                    return None;
+                }
+            }
+        }
+        let prelude_end = self.pos - 1;
+        let block_start = self.pos;
+        self.skip_block(Token::CloseCurly);
+        let block_end = match self.tokens[..self.pos].last() {
+            Some(&(_, Token::CloseCurly)) if self.pos > block_start => self.pos - 1,
+            _ => self.pos,
+        };
+        let after_rule = self.pos;
+
+        let selectors = self.within(prelude_start, prelude_end, Parser::parse_selectors);
+        let rule = match selectors {
+            Ok(selectors) => {
+                Some(Rule {
+                    selectors,
+                    declarations: self.within(block_start, block_end, Parser::parse_declarations),
+                })
+            }
+            Err(error) => {
+                self.warnings.push(error);
+                None
+            }
+        };
+        self.pos = after_rule;
+        rule
+    }
+
+    /// Run `parse` on the tokens from `start` up to `end` only.
+    fn within<T, F>(&mut self, start: usize, end: usize, parse: F) -> T
+        where F: FnOnce(&mut Parser) -> T
+    {
+        let outer_end = self.end;
+        self.pos = start;
+        self.end = end;
+        let result = parse(self);
+        self.end = outer_end;
+        result
+    }
+
+    fn parse_selectors(&mut self) -> Result<Vec<Selector>, ParseError> {
+        let mut selectors = Vec::new();
+        self.skip_whitespace();
+        loop {
+            selectors.push(self.parse_selector()?);
+            match self.peek() {
+                Some(&Token::Comma) => {
+                    self.next();
+                    self.skip_whitespace();
+                }
+                None => break,
+                Some(_) => return Err(self.error("',' or '{' in selector list")),
+            }
+        }
+        selectors.sort_by_key(|s| std::cmp::Reverse(s.specificity()));
+        Ok(selectors)
+    }
+
+    /// Parse a selector made of simple selectors joined by combinators.
+    fn parse_selector(&mut self) -> Result<Selector, ParseError> {
+        let mut selector = Selector::Simple(self.parse_simple_selector()?);
+        loop {
+            let after_whitespace = self.peek() == Some(&Token::Whitespace);
+            self.skip_whitespace();
+            if selector.pseudo_element().is_some() {
+                match self.peek() {
+                    None | Some(&Token::Comma) => break,
+                    Some(_) => return Err(self.error("',' or '{' after a pseudo-element")),
+                }
+            }
+            let combinator = match self.peek() {
+                Some(&Token::Delim('>')) => Combinator::Child,
+                Some(&Token::Delim('+')) => Combinator::NextSibling,
+                Some(&Token::Delim('~')) => Combinator::SubsequentSibling,
+                None | Some(&Token::Comma) => break,
+                Some(_) if after_whitespace => {
+                    let right = self.parse_simple_selector()?;
+                    selector = Selector::Complex(Box::new(selector), Combinator::Descendant, right);
+                    continue;
+                }
+                Some(_) => break,
+            };
+            self.next();
+            self.skip_whitespace();
+            let right = self.parse_simple_selector()?;
+            selector = Selector::Complex(Box::new(selector), combinator, right);
+        }
+        Ok(selector)
+    }
+
+    fn parse_simple_selector(&mut self) -> Result<SimpleSelector, ParseError> {
+        let mut selector = SimpleSelector {
+            tag_name: None,
+            id: None,
+            class: Vec::new(),
+            attributes: Vec::new(),
+            pseudo_classes: Vec::new(),
+            pseudo_element: None,
+        };
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some(&Token::Hash { ref value, is_id: true }) => selector.id = Some(value.clone()),
+                Some(&Token::Delim('.')) => {
+                    self.next();
+                    match self.peek() {
+                        Some(Token::Ident(name)) => selector.class.push(name.clone()),
+                        _ => return Err(self.error("a class name")),
+                    }
+                }
+                Some(&Token::OpenSquare) => {
+                    selector.attributes.push(self.parse_attribute_selector()?);
+                    continue;
+                }
+                Some(&Token::Colon) => {
+                    if let Some(pseudo_element) = self.parse_pseudo_element()? {
+                        selector.pseudo_element = Some(pseudo_element);
+                        break;
+                    }
+                    selector.pseudo_classes.push(self.parse_pseudo_class()?);
+                    continue;
+                }
+                Some(&Token::Delim('*')) => {}
+                Some(Token::Ident(name)) => selector.tag_name = Some(name.clone()),
+                _ => break,
+            }
+            self.next();
+        }
+        if self.pos == start {
+            return Err(self.error("a selector"));
+        }
+        Ok(selector)
+    }
+
+    /// Parse an attribute selector, from its `[` up to and including its `]`.
+    fn parse_attribute_selector(&mut self) -> Result<AttributeSelector, ParseError> {
+        self.expect(&Token::OpenSquare, "'['")?;
+        self.skip_whitespace();
+        let name = match self.peek() {
+            Some(Token::Ident(name)) => name.clone(),
+            _ => return Err(self.error("an attribute name")),
+        };
+        self.next();
+        self.skip_whitespace();
+        let operator = match self.peek() {
+            Some(&Token::CloseSquare) => {
+                self.next();
+                return Ok(AttributeSelector {
+                    name,
+                    operator: AttributeOperator::Exists,
+                    value: String::new(),
+                    case_sensitivity: CaseSensitivity::Default,
+                });
+            }
+            Some(&Token::Delim('=')) => AttributeOperator::Equals,
+            Some(&Token::Delim(c)) => {
+                let operator = match c {
+                    '~' => AttributeOperator::Includes,
+                    '|' => AttributeOperator::DashMatch,
+                    '^' => AttributeOperator::Prefix,
+                    '$' => AttributeOperator::Suffix,
+                    '*' => AttributeOperator::Substring,
+                    _ => return Err(self.error("an attribute selector operator")),
+                };
+                self.next();
+                if self.peek() != Some(&Token::Delim('=')) {
+                    return Err(self.error("'='"));
+                }
+                operator
+            }
+            _ => return Err(self.error("']' or an attribute selector operator")),
+        };
+        self.next();
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some(Token::Ident(value)) | Some(Token::String(value)) => value.clone(),
+            _ => return Err(self.error("an attribute value")),
+        };
+        self.next();
+        self.skip_whitespace();
+        let case_sensitivity = match self.peek() {
+            Some(Token::Ident(flag)) => match &*flag.to_ascii_lowercase() {
+                "i" => CaseSensitivity::Insensitive,
+                "s" => CaseSensitivity::Sensitive,
+                _ => CaseSensitivity::Default,
+            },
+            _ => CaseSensitivity::Default,
+        };
+        if case_sensitivity != CaseSensitivity::Default {
+            self.next();
+            self.skip_whitespace();
+        }
+        self.expect(&Token::CloseSquare, "']'")?;
+        Ok(AttributeSelector {
+            name,
+            operator,
+            value,
+            case_sensitivity,
+        })
+    }
+
+    /// Parse a pseudo-element, either as `::before` or in the legacy form `:before`. Returns
+    /// `None` without consuming anything if the next tokens start a pseudo-class instead.
+    fn parse_pseudo_element(&mut self) -> Result<Option<PseudoElement>, ParseError> {
+        let double_colon = self.peek_at(1) == Some(&Token::Colon);
+        let name = match self.peek_at(if double_colon { 2 } else { 1 }) {
+            Some(Token::Ident(name)) => name.to_ascii_lowercase(),
+            _ if double_colon => {
+                self.pos += 2;
+                return Err(self.error("a pseudo-element"));
+            }
+            _ => return Ok(None),
+        };
+        let pseudo_element = match &*name {
+            "before" => PseudoElement::Before,
+            "after" => PseudoElement::After,
+            _ if double_colon => {
+                self.pos += 2;
+                return Err(self.error("a supported pseudo-element"));
+            }
+            _ => return Ok(None),
+        };
+        self.pos += if double_colon { 3 } else { 2 };
+        Ok(Some(pseudo_element))
+    }
+
+    /// Parse a pseudo-class, from its `:` up to and including any closing parenthesis.
+    fn parse_pseudo_class(&mut self) -> Result<PseudoClass, ParseError> {
+        self.expect(&Token::Colon, "':'")?;
+        let name = match self.peek() {
+            Some(Token::Ident(name)) => name.to_ascii_lowercase(),
+            Some(&Token::Function(_)) => return self.parse_functional_pseudo_class(),
+            _ => return Err(self.error("a pseudo-class")),
+        };
+        let pseudo_class = match &*name {
+            "root" => PseudoClass::Root,
+            "empty" => PseudoClass::Empty,
+            "first-child" => PseudoClass::FirstChild,
+            "last-child" => PseudoClass::LastChild,
+            "only-child" => PseudoClass::OnlyChild,
+            "first-of-type" => PseudoClass::FirstOfType,
+            "last-of-type" => PseudoClass::LastOfType,
+            "only-of-type" => PseudoClass::OnlyOfType,
+            _ => return Err(self.error("a supported pseudo-class")),
+        };
+        self.next();
+        Ok(pseudo_class)
+    }
+
+    fn parse_functional_pseudo_class(&mut self) -> Result<PseudoClass, ParseError> {
+        let name = match self.peek() {
+            Some(Token::Function(name)) => name.to_ascii_lowercase(),
+            _ => return Err(self.error("a pseudo-class")),
+        };
+        let parse_argument: fn(&mut Parser) -> Result<PseudoClass, ParseError> = match &*name {
+            "nth-child" => {
+                |parser| parser.parse_nth_of().map(|(nth, of)| PseudoClass::NthChild(nth, of))
+            }
+            "nth-last-child" => {
+                |parser| parser.parse_nth_of().map(|(nth, of)| PseudoClass::NthLastChild(nth, of))
+            }
+            "nth-of-type" => |parser| parser.parse_nth().map(PseudoClass::NthOfType),
+            "nth-last-of-type" => |parser| parser.parse_nth().map(PseudoClass::NthLastOfType),
+            "not" => |parser| parser.parse_selector_argument().map(PseudoClass::Not),
+            "is" => |parser| parser.parse_selector_argument().map(PseudoClass::Is),
+            "where" => |parser| parser.parse_selector_argument().map(PseudoClass::Where),
+            "has" => |parser| parser.parse_relative_selectors().map(PseudoClass::Has),
+            _ => return Err(self.error("a supported pseudo-class")),
+        };
+        self.parse_function_arguments(parse_argument)
+    }
+
+    /// Run `parse` on the arguments of the function token at the current position, which must
+    /// consume all of them, and continue after the closing parenthesis.
+    fn parse_function_arguments<T, F>(&mut self, parse: F) -> Result<T, ParseError>
+        where F: FnOnce(&mut Parser) -> Result<T, ParseError>
+    {
+        self.next();
+        let start = self.pos;
+        self.skip_block(Token::CloseParen);
+        let end = match self.tokens[..self.pos].last() {
+            Some(&(_, Token::CloseParen)) if self.pos > start => self.pos - 1,
+            _ => self.pos,
+        };
+        let after = self.pos;
+        let result = self.within(start, end, |parser| {
+            let result = parse(parser)?;
+            parser.skip_whitespace();
+            match parser.peek() {
+                None => Ok(result),
+                Some(_) => Err(parser.error("')'")),
+            }
+        })?;
+        self.pos = after;
+        Ok(result)
+    }
+
+    /// Parse a selector list given to a pseudo-class, in which pseudo-elements are not allowed.
+    fn parse_selector_argument(&mut self) -> Result<Vec<Selector>, ParseError> {
+        let start = self.pos;
+        let selectors = self.parse_selectors()?;
+        if selectors.iter().any(|selector| selector.pseudo_element().is_some()) {
+            self.pos = start;
+            return Err(self.error("a selector without a pseudo-element"));
+        }
+        Ok(selectors)
+    }
+
+    /// Parse `An+B`, optionally followed by `of` and a selector list.
+    fn parse_nth_of(&mut self) -> Result<(Nth, Vec<Selector>), ParseError> {
+        let start = self.pos;
+        let of = (start..self.end).find(|&i| match self.tokens[i].1 {
+            Token::Ident(ref name) => name.eq_ignore_ascii_case("of"),
+            _ => false,
+        });
+        match of {
+            Some(of) => {
+                let nth = self.within(start, of, Parser::parse_nth)?;
+                self.pos = of + 1;
+                Ok((nth, self.parse_selector_argument()?))
+            }
+            None => Ok((self.parse_nth()?, Vec::new())),
+        }
+    }
+
+    /// Parse the `An+B` syntax, or `odd` or `even`, from all the remaining tokens.
+    ///
+    /// White space is only allowed around the sign before `B`, as in `2n + 1`.
+    fn parse_nth(&mut self) -> Result<Nth, ParseError> {
+        self.skip_whitespace();
+        let start = self.offset();
+        let source = match self.tokens.get(self.end) {
+            Some(&(offset, _)) => &self.input[start..offset],
+            None => &self.input[start..],
+        };
+        let text = source.trim_end().to_ascii_lowercase();
+        let parse_integer = |s: &str| s.parse::<i32>().ok();
+        let nth = match &*text {
+            "odd" => Some(Nth { a: 2, b: 1 }),
+            "even" => Some(Nth { a: 2, b: 0 }),
+            _ => match text.find('n') {
+                Some(n) => {
+                    let a = match &text[..n] {
+                        "" | "+" => Some(1),
+                        "-" => Some(-1),
+                        a => parse_integer(a),
+                    };
+                    let b = text[n + 1..].trim_start();
+                    let b = match b.chars().next() {
+                        None => Some(0),
+                        Some(sign @ '+') | Some(sign @ '-') => {
+                            let digits = b[1..].trim_start();
+                            if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+                                parse_integer(&format!("{}{}", sign, digits))
+                            } else {
+                                None
+                            }
+                        }
+                        Some(_) => None,
+                    };
+                    a.and_then(|a| b.map(|b| Nth { a, b }))
+                }
+                None => parse_integer(&text).map(|b| Nth { a: 0, b }),
+            },
+        };
+        match nth {
+            Some(nth) => {
+                self.pos = self.end;
+                Ok(nth)
+            }
+            None => Err(self.error("An+B")),
+        }
+    }
+
+    /// Parse the argument of `:has()`: a list of selectors that may start with a combinator.
+    fn parse_relative_selectors(&mut self) -> Result<Vec<RelativeSelector>, ParseError> {
+        let mut selectors = Vec::new();
+        self.skip_whitespace();
+        loop {
+            let combinator = match self.peek() {
+                Some(&Token::Delim('>')) => Combinator::Child,
+                Some(&Token::Delim('+')) => Combinator::NextSibling,
+                Some(&Token::Delim('~')) => Combinator::SubsequentSibling,
+                _ => Combinator::Descendant,
+            };
+            if combinator != Combinator::Descendant {
+                self.next();
+                self.skip_whitespace();
+            }
+            let selector = self.parse_selector()?;
+            if selector.pseudo_element().is_some() {
+                return Err(self.error("a selector without a pseudo-element"));
+            }
+            selectors.push(RelativeSelector {
+                combinator,
+                selector,
+            });
+            match self.peek() {
+                Some(&Token::Comma) => {
+                    self.next();
+                    self.skip_whitespace();
+                }
+                None => break,
+                Some(_) => return Err(self.error("',' or ')'")),
+            }
+        }
+        Ok(selectors)
+    }
+
+    /// Parse the declarations in a block, skipping invalid ones.
+    fn parse_declarations(&mut self) -> Vec<Declaration> {
+        let mut declarations = Vec::new();
+        loop {
+            match self.peek() {
+                None => break,
+                Some(&Token::Whitespace) | Some(&Token::Semicolon) => {
+                    self.next();
+                }
+                Some(_) => {
+                    let start = self.pos;
+                    while let Some(token) = self.next() {
+                        if token == Token::Semicolon {
+                            self.pos -= 1;
+                            break;
+                        }
+                        self.skip_nested(&token);
+                    }
+                    let end = self.pos;
+                    match self.within(start, end, Parser::parse_declaration) {
+                        Ok(parsed) => declarations.extend(parsed),
+                        Err(error) => self.warnings.push(error),
+                    }
+                    self.pos = end;
+                }
+            }
+        }
+        declarations
+    }
+
+    /// Parse one declaration. Box shorthands such as `margin` are expanded into a declaration for
+    /// each of their longhands, so that the cascade weighs them against other longhands.
+    fn parse_declaration(&mut self) -> Result<Vec<Declaration>, ParseError> {
+        let property_name = match self.peek() {
+            Some(Token::Ident(name)) => name.to_ascii_lowercase(),
+            _ => return Err(self.error("a property name")),
+        };
+        self.next();
+        self.skip_whitespace();
+        self.expect(&Token::Colon, "':'")?;
+        self.skip_whitespace();
+        let longhands = box_longhands(&property_name);
+        let values = match &*property_name {
+            "content" => vec![self.parse_content()?],
+            "counter-reset" => vec![self.parse_counter_changes(0)?],
+            "counter-increment" => vec![self.parse_counter_changes(1)?],
+            _ if longhands.is_some() => self.parse_box_sides()?.to_vec(),
+            _ => vec![self.parse_value()?],
+        };
+        self.skip_whitespace();
+        let important = self.peek() == Some(&Token::Delim('!'));
+        if important {
+            self.next();
+            self.skip_whitespace();
+            match self.peek() {
+                Some(Token::Ident(name)) if name.eq_ignore_ascii_case("important") => self.next(),
+                _ => return Err(self.error("'important'")),
+            };
+            self.skip_whitespace();
+        }
+        if self.peek().is_some() {
+            return Err(self.error("';'"));
+        }
+
+        let names = longhands.map_or_else(|| vec![property_name], |names| names.to_vec());
+        Ok(names.into_iter()
+            .zip(values)
+            .map(|(name, value)| Declaration { name, value, important })
+            .collect())
+    }
+
+    /// Parse the one to four values of a box shorthand, and expand them to the top, right,
+    /// bottom and left sides.
+    fn parse_box_sides(&mut self) -> Result<[Value; 4], ParseError> {
+        let mut values = vec![self.parse_value()?];
+        loop {
+            self.skip_whitespace();
+            if matches!(self.peek(), None | Some(&Token::Delim('!'))) {
+                break;
+            }
+            if values.len() == 4 || is_css_wide_keyword(&values[0]) {
+                return Err(self.error("';'"));
+            }
+            let value = self.parse_value()?;
+            if is_css_wide_keyword(&value) {
+                return Err(self.error("a length"));
+            }
+            values.push(value);
+        }
+        let side = |index: usize| values[index].clone();
+        Ok(match values.len() {
+            1 => [side(0), side(0), side(0), side(0)],
+            2 => [side(0), side(1), side(0), side(1)],
+            3 => [side(0), side(1), side(2), side(1)],
+            _ => [side(0), side(1), side(2), side(3)],
+        })
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        let value = match self.peek() {
+            Some(&Token::Dimension { value, ref unit, .. }) => {
+                match &*unit.to_ascii_lowercase() {
+                    "px" => Value::Length(value, Unit::Px),
+                    _ => return Err(self.error("a unit")),
+                }
+            }
+            Some(&Token::Number { value: 0.0, .. }) => Value::Length(0.0, Unit::Px),
+            Some(&Token::Number { .. }) => return Err(self.error("a unit")),
+            Some(Token::Hash { value, .. }) => match parse_hex_color(value) {
+                Some(color) => Value::ColorValue(color),
+                None => return Err(self.error("a hexadecimal color")),
+            },
+            Some(Token::Ident(keyword)) => Value::Keyword(keyword.to_ascii_lowercase()),
+            _ => return Err(self.error("a value")),
+        };
+        self.next();
+        Ok(value)
+    }
+
+    /// Parse the value of the `content` property.
+    fn parse_content(&mut self) -> Result<Value, ParseError> {
+        if let Some(Token::Ident(keyword)) = self.peek() {
+            let keyword = keyword.to_ascii_lowercase();
+            if keyword == "none" || keyword == "normal" {
+                self.next();
+                return Ok(Value::Keyword(keyword));
+            }
+        }
+        let mut items = Vec::new();
+        loop {
+            let item = match self.peek() {
+                None => break,
+                Some(Token::String(string)) => {
+                    let item = ContentItem::String(string.clone());
+                    self.next();
+                    item
+                }
+                Some(Token::Function(name)) if name.eq_ignore_ascii_case("attr") => {
+                    self.parse_function_arguments(|parser| {
+                        parser.skip_whitespace();
+                        parser.parse_ident("an attribute name").map(ContentItem::Attr)
+                    })?
+                }
+                Some(Token::Function(name)) if name.eq_ignore_ascii_case("counter") => {
+                    self.parse_function_arguments(|parser| {
+                        parser.skip_whitespace();
+                        let name = parser.parse_ident("a counter name")?;
+                        let style = parser.parse_counter_style()?;
+                        Ok(ContentItem::Counter(name, style))
+                    })?
+                }
+                Some(Token::Function(name)) if name.eq_ignore_ascii_case("counters") => {
+                    self.parse_function_arguments(|parser| {
+                        parser.skip_whitespace();
+                        let name = parser.parse_ident("a counter name")?;
+                        parser.skip_whitespace();
+                        parser.expect(&Token::Comma, "','")?;
+                        parser.skip_whitespace();
+                        let separator = match parser.peek() {
+                            Some(Token::String(separator)) => separator.clone(),
+                            _ => return Err(parser.error("a separator string")),
+                        };
+                        parser.next();
+                        let style = parser.parse_counter_style()?;
+                        Ok(ContentItem::Counters(name, separator, style))
+                    })?
+                }
+                _ => return Err(self.error("a string, attr() or a counter")),
+            };
+            items.push(item);
+            self.skip_whitespace();
+        }
+        if items.is_empty() {
+            return Err(self.error("a value"));
+        }
+        Ok(Value::Content(items))
+    }
+
+    /// Parse the optional `, style` at the end of `counter()` and `counters()`.
+    fn parse_counter_style(&mut self) -> Result<String, ParseError> {
+        self.skip_whitespace();
+        if self.peek() != Some(&Token::Comma) {
+            return Ok("decimal".to_string());
+        }
+        self.next();
+        self.skip_whitespace();
+        self.parse_ident("a counter style").map(|style| style.to_ascii_lowercase())
+    }
+
+    /// Parse the value of `counter-reset` or `counter-increment`: `none`, or a list of counter
+    /// names each optionally followed by an integer, which otherwise defaults to `default`.
+    fn parse_counter_changes(&mut self, default: i32) -> Result<Value, ParseError> {
+        if let Some(Token::Ident(keyword)) = self.peek() {
+            if keyword.eq_ignore_ascii_case("none") {
+                self.next();
+                return Ok(Value::Keyword("none".to_string()));
+            }
+        }
+        let mut counters = Vec::new();
+        while self.peek().is_some() {
+            let name = self.parse_ident("a counter name")?;
+            self.skip_whitespace();
+            let value = match self.peek() {
+                Some(&Token::Number { value, integer: true }) => {
+                    self.next();
+                    self.skip_whitespace();
+                    // Values too large for an `i32` are clamped to its range.
+                    value.clamp(i32::MIN as f32, i32::MAX as f32) as i32
+                }
+                _ => default,
+            };
+            counters.push((name, value));
+        }
+        if counters.is_empty() {
+            return Err(self.error("a counter name"));
+        }
+        Ok(Value::Counters(counters))
+    }
+
+    fn parse_ident(&mut self, description: &str) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(Token::Ident(ident)) => {
+                let ident = ident.clone();
+                self.next();
+                Ok(ident)
+            }
+            _ => Err(self.error(description)),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek() == Some(&Token::Whitespace) {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: &Token, description: &str) -> Result<(), ParseError> {
+        if self.peek() == Some(expected) {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.error(description))
+        }
+    }
+
+    fn error(&self, expected: &str) -> ParseError {
+        ParseError::new(&self.input, self.offset(), expected.to_string())
+    }
+
+    /// The byte offset of the next token, or the length of the input at the end.
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.input.len(), |&(offset, _)| offset)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peek_at(0)
+    }
+
+    /// The token `n` places after the next one.
+    fn peek_at(&self, n: usize) -> Option<&Token> {
+        self.tokens[..self.end].get(self.pos + n).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+}
+
+/// The longhands set by the `margin`, `padding` and `border-width` shorthands, in the order top,
+/// right, bottom, left.
+fn box_longhands(name: &str) -> Option<[String; 4]> {
+    let (prefix, suffix) = match name {
+        "margin" => ("margin", ""),
+        "padding" => ("padding", ""),
+        "border-width" => ("border", "-width"),
+        _ => return None,
+    };
+    Some(["top", "right", "bottom", "left"].map(|side| format!("{}-{}{}", prefix, side, suffix)))
+}
+
+/// Whether `value` is one of the keywords that every property accepts, which must stand alone.
+fn is_css_wide_keyword(value: &Value) -> bool {
+    matches!(value, Value::Keyword(keyword) if keyword == "inherit" || keyword == "initial" ||
+                                               keyword == "unset")
+}
+
+/// Parse the digits of a `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa` color.
+fn parse_hex_color(digits: &str) -> Option<Color> {
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize, len: usize| {
+        let value = u8::from_str_radix(&digits[i * len..(i + 1) * len], 16).unwrap();
+        if len == 1 { value * 17 } else { value }
+    };
+    let (len, has_alpha) = match digits.len() {
+        3 => (1, false),
+        4 => (1, true),
+        6 => (2, false),
+        8 => (2, true),
+        _ => return None,
+    };
+    Some(Color {
+        r: channel(0, len),
+        g: channel(1, len),
+        b: channel(2, len),
+        a: if has_alpha { channel(3, len) } else { 255 },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_with_warnings, Value};
+
+    /// The declarations of each rule in `source`, as `name: value`, and where each warning is.
+    fn parse(source: &str) -> (Vec<Vec<String>>, Vec<(usize, usize)>) {
+        let (stylesheet, warnings) = parse_with_warnings(source.to_string());
+        let rules = stylesheet.rules
+            .iter()
+            .map(|rule| {
+                rule.declarations
+                    .iter()
+                    .map(|declaration| match declaration.value {
+                        Value::Keyword(ref keyword) => {
+                            format!("{}: {}", declaration.name, keyword)
+                        }
+                        ref value => format!("{}: {:?}", declaration.name, value),
+                    })
+                    .collect()
+            })
+            .collect();
+        (rules, warnings.iter().map(|warning| (warning.line, warning.column)).collect())
+    }
+
+    #[test]
+    fn invalid_declaration_is_dropped_up_to_the_next_semicolon() {
+        assert_eq!(parse("p { color: red; width: ; height: auto }").0,
+                   [["color: red", "height: auto"]]);
+        assert_eq!(parse("p { color: red; : x; width: auto }").0, [["color: red", "width: auto"]]);
+        // A semicolon inside a block or function does not end the declaration.
+        assert_eq!(parse("p { 12px: x; color: red; display: block (;) ; font-style: italic; }").0,
+                   [["color: red", "font-style: italic"]]);
+        assert_eq!(parse("p { color: red; display: [;] ; width: f(;) auto }").0,
+                   [["color: red"]]);
+    }
+
+    #[test]
+    fn rule_with_invalid_selector_is_dropped_up_to_its_end() {
+        assert_eq!(parse("p > { color: red; } em { color: blue }").0, [["color: blue"]]);
+        assert_eq!(parse("p) { color: red } em { color: blue }").0, [["color: blue"]]);
+        // Blocks nested in the rule's prelude or body do not end it.
+        assert_eq!(parse("p:nope { color: red; x { y: z; } } em { color: blue }").0,
+                   [["color: blue"]]);
+        assert_eq!(parse("p:is(a { b }) { color: red } em { color: blue }").0,
+                   [["color: blue"]]);
+        assert_eq!(parse("p[a{]}] { color: red } em { color: blue }").0, [["color: blue"]]);
+        // An unclosed block runs to the end of the input.
+        assert_eq!(parse("p[a{] { color: red } em { color: blue }").0.len(), 0);
+    }
+
+    #[test]
+    fn at_rules_are_skipped() {
+        let (rules, warnings) =
+            parse("@media screen { p { color: red } } @import 'x'; em { color: blue }");
+        assert_eq!(rules, [["color: blue"]]);
+        assert_eq!(warnings.len(), 0);
+        assert_eq!(parse("@font-face { src: url(x) } @charset \"utf-8\"; em { color: blue }").0,
+                   [["color: blue"]]);
+        assert_eq!(parse("@media (x: ;) { p { color: red } } em { color: blue }").0,
+                   [["color: blue"]]);
+    }
+
+    #[test]
+    fn warning_positions() {
+        assert_eq!(parse("p { color: red; width: ; height: auto }").1, [(1, 24)]);
+        assert_eq!(parse("p { 12px: x; color: red }").1, [(1, 5)]);
+        assert_eq!(parse("p > { color: red; } em { color: blue }").1, [(1, 5)]);
+        let (rules, warnings) = parse("p {\n  color: red;\n  width: 1px 2px;\n}\n  p! { }\nem");
+        assert_eq!(rules, [["color: red"]]);
+        // The second value, the `!`, and the end of the input where a `{` was expected.
+        assert_eq!(warnings, [(3, 14), (5, 4), (6, 3)]);
+        // Columns count characters, and CRLF counts as one line break.
+        assert_eq!(parse("\r\n/* é */ p> {}").1, [(2, 12)]);
+    }
+}
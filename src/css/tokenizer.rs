@@ -0,0 +1,516 @@
+/// A CSS token, as defined by CSS Syntax Level 3.
+///
+/// Comments are dropped by the tokenizer and never appear as tokens.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Ident(String),
+    /// A name followed by `(`, such as `rgb(`. The name does not include the parenthesis.
+    Function(String),
+    AtKeyword(String),
+    /// `#` followed by a name. `is_id` is set if the name would also be a valid identifier, as
+    /// required for ID selectors.
+    Hash { value: String, is_id: bool },
+    String(String),
+    /// A string interrupted by an unescaped newline.
+    BadString,
+    /// An unquoted `url(...)`. Quoted URLs are a `url` function containing a string.
+    Url(String),
+    BadUrl,
+    Delim(char),
+    /// A number; `integer` is set if it was written without a fraction or exponent.
+    Number { value: f32, integer: bool },
+    Percentage(f32),
+    Dimension { value: f32, integer: bool, unit: String },
+    Whitespace,
+    /// `<!--`
+    Cdo,
+    /// `-->`
+    Cdc,
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquare,
+    CloseSquare,
+    OpenParen,
+    CloseParen,
+    OpenCurly,
+    CloseCurly,
+}
+
+/// Splits CSS source into [`Token`]s.
+///
+/// As an iterator, it yields each token together with the byte offset where it starts.
+pub struct Tokenizer<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    /// A tokenizer for `input`, which must already have had its newlines normalized by
+    /// [`preprocess`].
+    pub fn new(input: &'a str) -> Tokenizer<'a> {
+        Tokenizer { input, pos: 0 }
+    }
+
+    /// Return the next token, or `None` at the end of the input.
+    pub fn next_token(&mut self) -> Option<Token> {
+        self.consume_comments();
+        let c = self.peek(0)?;
+        let token = match c {
+            c if is_whitespace(c) => {
+                self.consume_while(is_whitespace);
+                return Some(Token::Whitespace);
+            }
+            '"' | '\'' => {
+                self.consume_char();
+                self.consume_string(c)
+            }
+            '#' => {
+                self.consume_char();
+                if self.peek(0).is_some_and(is_ident_char) || self.starts_escape(0) {
+                    let is_id = self.starts_identifier(0);
+                    Token::Hash {
+                        value: self.consume_name(),
+                        is_id,
+                    }
+                } else {
+                    Token::Delim('#')
+                }
+            }
+            '(' => self.single(Token::OpenParen),
+            ')' => self.single(Token::CloseParen),
+            '[' => self.single(Token::OpenSquare),
+            ']' => self.single(Token::CloseSquare),
+            '{' => self.single(Token::OpenCurly),
+            '}' => self.single(Token::CloseCurly),
+            ',' => self.single(Token::Comma),
+            ':' => self.single(Token::Colon),
+            ';' => self.single(Token::Semicolon),
+            '+' | '.' if self.starts_number(0) => self.consume_numeric(),
+            '-' if self.starts_number(0) => self.consume_numeric(),
+            '-' if self.input[self.pos..].starts_with("-->") => {
+                self.pos += 3;
+                Token::Cdc
+            }
+            '-' if self.starts_identifier(0) => self.consume_ident_like(),
+            '<' if self.input[self.pos..].starts_with("<!--") => {
+                self.pos += 4;
+                Token::Cdo
+            }
+            '@' if self.starts_identifier(1) => {
+                self.consume_char();
+                Token::AtKeyword(self.consume_name())
+            }
+            '\\' if self.starts_escape(0) => self.consume_ident_like(),
+            '0'..='9' => self.consume_numeric(),
+            c if is_ident_start(c) => self.consume_ident_like(),
+            c => self.single(Token::Delim(c)),
+        };
+        Some(token)
+    }
+
+    fn single(&mut self, token: Token) -> Token {
+        self.consume_char();
+        token
+    }
+
+    fn consume_comments(&mut self) {
+        while self.input[self.pos..].starts_with("/*") {
+            match self.input[self.pos + 2..].find("*/") {
+                Some(end) => self.pos += end + 4,
+                None => self.pos = self.input.len(),
+            }
+        }
+    }
+
+    /// Consume a string token whose opening quote has already been consumed.
+    fn consume_string(&mut self, quote: char) -> Token {
+        let mut value = String::new();
+        loop {
+            match self.peek(0) {
+                None => break,
+                Some(c) if c == quote => {
+                    self.consume_char();
+                    break;
+                }
+                Some('\n') => return Token::BadString,
+                Some('\\') => {
+                    match self.peek(1) {
+                        None => {
+                            self.consume_char();
+                        }
+                        Some('\n') => self.pos += 2,
+                        Some(_) => {
+                            self.consume_char();
+                            value.push(self.consume_escape());
+                        }
+                    }
+                }
+                Some(c) => {
+                    self.consume_char();
+                    value.push(c);
+                }
+            }
+        }
+        Token::String(value)
+    }
+
+    fn consume_numeric(&mut self) -> Token {
+        let (value, integer) = self.consume_number();
+        if self.starts_identifier(0) {
+            Token::Dimension {
+                value,
+                integer,
+                unit: self.consume_name(),
+            }
+        } else if self.peek(0) == Some('%') {
+            self.consume_char();
+            Token::Percentage(value)
+        } else {
+            Token::Number { value, integer }
+        }
+    }
+
+    fn consume_number(&mut self) -> (f32, bool) {
+        let start = self.pos;
+        let mut integer = true;
+        if let Some('+') | Some('-') = self.peek(0) {
+            self.consume_char();
+        }
+        self.consume_while(|c| c.is_ascii_digit());
+        if self.peek(0) == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_digit()) {
+            integer = false;
+            self.consume_char();
+            self.consume_while(|c| c.is_ascii_digit());
+        }
+        if let Some('e') | Some('E') = self.peek(0) {
+            let digit_at = match self.peek(1) {
+                Some('+') | Some('-') => 2,
+                _ => 1,
+            };
+            if self.peek(digit_at).is_some_and(|c| c.is_ascii_digit()) {
+                integer = false;
+                self.pos += digit_at;
+                self.consume_while(|c| c.is_ascii_digit());
+            }
+        }
+        let text = self.input[start..self.pos].trim_start_matches('+');
+        (text.parse().unwrap_or(0.0), integer)
+    }
+
+    fn consume_ident_like(&mut self) -> Token {
+        let name = self.consume_name();
+        if self.peek(0) != Some('(') {
+            return Token::Ident(name);
+        }
+        self.consume_char();
+        if !name.eq_ignore_ascii_case("url") {
+            return Token::Function(name);
+        }
+        let rest = self.input[self.pos..].trim_start_matches(is_whitespace);
+        if rest.starts_with('"') || rest.starts_with('\'') {
+            Token::Function(name)
+        } else {
+            self.consume_url()
+        }
+    }
+
+    /// Consume an unquoted URL whose `url(` has already been consumed.
+    fn consume_url(&mut self) -> Token {
+        let mut value = String::new();
+        self.consume_while(is_whitespace);
+        loop {
+            match self.peek(0) {
+                None => return Token::Url(value),
+                Some(')') => {
+                    self.consume_char();
+                    return Token::Url(value);
+                }
+                Some(c) if is_whitespace(c) => {
+                    self.consume_while(is_whitespace);
+                    match self.peek(0) {
+                        None => return Token::Url(value),
+                        Some(')') => {
+                            self.consume_char();
+                            return Token::Url(value);
+                        }
+                        Some(_) => return self.consume_bad_url(),
+                    }
+                }
+                Some('"') | Some('\'') | Some('(') => return self.consume_bad_url(),
+                Some(c) if is_non_printable(c) => return self.consume_bad_url(),
+                Some('\\') if self.starts_escape(0) => {
+                    self.consume_char();
+                    value.push(self.consume_escape());
+                }
+                Some('\\') => return self.consume_bad_url(),
+                Some(c) => {
+                    self.consume_char();
+                    value.push(c);
+                }
+            }
+        }
+    }
+
+    /// Skip the rest of a malformed URL, up to and including its closing parenthesis.
+    fn consume_bad_url(&mut self) -> Token {
+        loop {
+            match self.peek(0) {
+                None => break,
+                Some(')') => {
+                    self.consume_char();
+                    break;
+                }
+                Some('\\') if self.starts_escape(0) => {
+                    self.consume_char();
+                    self.consume_escape();
+                }
+                Some(_) => {
+                    self.consume_char();
+                }
+            }
+        }
+        Token::BadUrl
+    }
+
+    fn consume_name(&mut self) -> String {
+        let mut name = String::new();
+        loop {
+            match self.peek(0) {
+                Some(c) if is_ident_char(c) => {
+                    self.consume_char();
+                    name.push(c);
+                }
+                Some('\\') if self.starts_escape(0) => {
+                    self.consume_char();
+                    name.push(self.consume_escape());
+                }
+                _ => return name,
+            }
+        }
+    }
+
+    /// Consume an escape whose backslash has already been consumed.
+    fn consume_escape(&mut self) -> char {
+        let c = match self.peek(0) {
+            Some(c) => c,
+            None => return '\u{fffd}',
+        };
+        if !c.is_ascii_hexdigit() {
+            self.consume_char();
+            return c;
+        }
+        let start = self.pos;
+        while self.pos - start < 6 && self.peek(0).is_some_and(|c| c.is_ascii_hexdigit()) {
+            self.consume_char();
+        }
+        let code = u32::from_str_radix(&self.input[start..self.pos], 16).unwrap();
+        if self.peek(0).is_some_and(is_whitespace) {
+            self.consume_char();
+        }
+        match ::std::char::from_u32(code) {
+            Some('\0') | None => '\u{fffd}',
+            Some(c) => c,
+        }
+    }
+
+    /// Whether the input `offset` characters ahead starts a valid escape.
+    fn starts_escape(&self, offset: usize) -> bool {
+        self.peek(offset) == Some('\\') && self.peek(offset + 1) != Some('\n')
+    }
+
+    /// Whether the input `offset` characters ahead starts an identifier.
+    fn starts_identifier(&self, offset: usize) -> bool {
+        match self.peek(offset) {
+            Some('-') => {
+                self.peek(offset + 1).is_some_and(|c| is_ident_start(c) || c == '-') ||
+                self.starts_escape(offset + 1)
+            }
+            Some('\\') => self.starts_escape(offset),
+            Some(c) => is_ident_start(c),
+            None => false,
+        }
+    }
+
+    /// Whether the input `offset` characters ahead starts a number.
+    fn starts_number(&self, offset: usize) -> bool {
+        let is_digit = |offset| self.peek(offset).is_some_and(|c: char| c.is_ascii_digit());
+        match self.peek(offset) {
+            Some('+') | Some('-') => {
+                is_digit(offset + 1) || self.peek(offset + 1) == Some('.') && is_digit(offset + 2)
+            }
+            Some('.') => is_digit(offset + 1),
+            Some(c) => c.is_ascii_digit(),
+            None => false,
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.input[self.pos..].chars().nth(offset)
+    }
+
+    fn consume_char(&mut self) -> char {
+        let c = self.peek(0).unwrap();
+        self.pos += c.len_utf8();
+        c
+    }
+
+    fn consume_while<F>(&mut self, test: F)
+        where F: Fn(char) -> bool
+    {
+        while self.peek(0).is_some_and(&test) {
+            self.consume_char();
+        }
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = (usize, Token);
+
+    fn next(&mut self) -> Option<(usize, Token)> {
+        self.consume_comments();
+        let start = self.pos;
+        self.next_token().map(|token| (start, token))
+    }
+}
+
+/// Normalize newlines and replace NUL characters, as CSS requires before tokenizing.
+pub fn preprocess(input: String) -> String {
+    if !input.contains(['\r', '\x0c', '\0']) {
+        return input;
+    }
+    input.replace("\r\n", "\n").replace(['\r', '\x0c'], "\n").replace('\0', "\u{fffd}")
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n')
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_ident_char(c: char) -> bool {
+    is_ident_start(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn is_non_printable(c: char) -> bool {
+    matches!(c, '\0'..='\x08' | '\x0b' | '\x0e'..='\x1f' | '\x7f')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Token::*;
+    use super::{preprocess, Token, Tokenizer};
+
+    fn tokens(source: &str) -> Vec<Token> {
+        Tokenizer::new(&preprocess(source.to_string())).map(|(_, token)| token).collect()
+    }
+
+    fn ident(name: &str) -> Token {
+        Ident(name.to_string())
+    }
+
+    fn string(value: &str) -> Token {
+        String(value.to_string())
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(tokens(r#""a\"b" 'c\'d"'"#),
+                   [string("a\"b"), Whitespace, string("c'd\"")]);
+        assert_eq!(tokens(r#""\41 x\9\
+y""#), [string("Ax\ty")]);
+        // A string is closed by the end of the input, but not by a newline.
+        assert_eq!(tokens("'open"), [string("open")]);
+        assert_eq!(tokens("'a\nb'"), [BadString, Whitespace, ident("b"), string("")]);
+        assert_eq!(tokens("'a\\"), [string("a")]);
+    }
+
+    #[test]
+    fn numbers() {
+        let number = |value, integer| Number { value, integer };
+        assert_eq!(tokens("12 +3 -4.5 .5 1e3 2E-2 -1.5e+1"),
+                   [number(12.0, true), Whitespace, number(3.0, true), Whitespace,
+                    number(-4.5, false), Whitespace, number(0.5, false), Whitespace,
+                    number(1000.0, false), Whitespace, number(0.02, false), Whitespace,
+                    number(-15.0, false)]);
+        // A dot or exponent without digits after it is not part of the number.
+        assert_eq!(tokens("1."), [number(1.0, true), Delim('.')]);
+        assert_eq!(tokens("+.5"), [number(0.5, false)]);
+        assert_eq!(tokens("+ 1"), [Delim('+'), Whitespace, number(1.0, true)]);
+    }
+
+    #[test]
+    fn dimensions_and_percentages() {
+        let dimension = |value, integer, unit: &str| {
+            Dimension { value, integer, unit: unit.to_string() }
+        };
+        assert_eq!(tokens("10px 1.5em 50% -2e1% 3e 4-x 5\\%"),
+                   [dimension(10.0, true, "px"), Whitespace, dimension(1.5, false, "em"),
+                    Whitespace, Percentage(50.0), Whitespace, Percentage(-20.0), Whitespace,
+                    dimension(3.0, true, "e"), Whitespace, dimension(4.0, true, "-x"),
+                    Whitespace, dimension(5.0, true, "%")]);
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(tokens("url( a.png ) URL(b\\)c)"),
+                   [Url("a.png".to_string()), Whitespace, Url("b)c".to_string())]);
+        assert_eq!(tokens("url( 'a.png' )"),
+                   [Function("url".to_string()), Whitespace, string("a.png"), Whitespace,
+                    CloseParen]);
+        assert_eq!(tokens("url(a b) x"), [BadUrl, Whitespace, ident("x")]);
+        assert_eq!(tokens("url(a\"b) url(a(b) url(a\\\nb)"),
+                   [BadUrl, Whitespace, BadUrl, Whitespace, BadUrl]);
+        assert_eq!(tokens("url(a"), [Url("a".to_string())]);
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(tokens("a/* x */b /**/ c"), [ident("a"), ident("b"), Whitespace, Whitespace,
+                                                ident("c")]);
+        assert_eq!(tokens("a /* unterminated"), [ident("a"), Whitespace]);
+        let offsets: Vec<usize> = Tokenizer::new("/* x */a/**/b").map(|(i, _)| i).collect();
+        assert_eq!(offsets, [7, 12]);
+    }
+
+    #[test]
+    fn cdo_and_cdc() {
+        assert_eq!(tokens("<!-- a --> <! --x"),
+                   [Cdo, Whitespace, ident("a"), Whitespace, Cdc, Whitespace, Delim('<'),
+                    Delim('!'), Whitespace, ident("--x")]);
+    }
+
+    #[test]
+    fn hashes() {
+        let hash = |value: &str, is_id| Hash { value: value.to_string(), is_id };
+        assert_eq!(tokens("#main #1a #-x #--y #\\31 #"),
+                   [hash("main", true), Whitespace, hash("1a", false), Whitespace,
+                    hash("-x", true), Whitespace, hash("--y", true), Whitespace,
+                    hash("1", true), Delim('#')]);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(tokens("\\66 oo \\@x"), [ident("foo"), Whitespace, ident("@x")]);
+        assert_eq!(tokens("\\1F600 "), [ident("\u{1f600}")]);
+        // Surrogates, NUL and code points beyond the Unicode range become U+FFFD.
+        assert_eq!(tokens("\\d800 \\0 \\110000 \\FFFFFF"),
+                   [ident("\u{fffd}\u{fffd}\u{fffd}\u{fffd}")]);
+        // A backslash at the end of the input escapes the end of the input.
+        assert_eq!(tokens("a\\"), [ident("a\u{fffd}")]);
+        assert_eq!(tokens("\\\n"), [Delim('\\'), Whitespace]);
+        assert_eq!(tokens("'\0'"), [string("\u{fffd}")]);
+    }
+
+    #[test]
+    fn punctuation_and_at_keywords() {
+        assert_eq!(tokens("@media{a:b;c,d}[e](f)@ @-"),
+                   [AtKeyword("media".to_string()), OpenCurly, ident("a"), Colon, ident("b"),
+                    Semicolon, ident("c"), Comma, ident("d"), CloseCurly, OpenSquare, ident("e"),
+                    CloseSquare, OpenParen, ident("f"), CloseParen, Delim('@'), Whitespace,
+                    Delim('@'), Delim('-')]);
+        assert_eq!(tokens("rgb(\r\n"), [Function("rgb".to_string()), Whitespace]);
+    }
+}
@@ -1,31 +1,901 @@
-struct Node {
-    children: Vec<Node>,
-    node_type: NodeType,
+//! The document object model: a tree of element, text and other markup nodes.
+//!
+//! Nodes live in an arena owned by a [`Document`] and are referred to by [`NodeId`] handles.
+//! Each node links to its parent and siblings, so the tree can be walked in any direction
+//! without borrowing the path that led to a node.
+
+use std::collections::{HashMap, HashSet};
+
+use html;
+
+/// A handle to a node in a [`Document`].
+///
+/// A handle stays valid for as long as its document does, including after the node has been
+/// moved elsewhere in the tree or detached from it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+/// A node in a document, together with its links to the surrounding nodes.
+pub struct Node {
+    pub node_type: NodeType,
+    parent: Option<NodeId>,
+    first_child: Option<NodeId>,
+    last_child: Option<NodeId>,
+    previous_sibling: Option<NodeId>,
+    next_sibling: Option<NodeId>,
 }
 
-enum NodeType {
+#[derive(Clone)]
+pub enum NodeType {
+    /// The root of a document. Its children are the doctype, any top-level comments and the
+    /// `html` element.
+    Document(DocumentData),
+    Doctype(DoctypeData),
     Text(String),
+    Comment(String),
+    ProcessingInstruction(ProcessingInstructionData),
     Element(ElementData),
 }
-struct ElementData {
-    tag_name: String,
-    attribute: AttrMap,
+
+#[derive(Clone)]
+pub struct DocumentData {
+    pub quirks_mode: QuirksMode,
+}
+
+/// The compatibility mode a document is rendered in, as selected by its doctype.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuirksMode {
+    NoQuirks,
+    LimitedQuirks,
+    Quirks,
+}
+
+#[derive(Clone)]
+pub struct DoctypeData {
+    pub name: String,
+    pub public_id: Option<String>,
+    pub system_id: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct ProcessingInstructionData {
+    pub target: String,
+    pub data: String,
+}
+
+/// The tag name and attributes of an element node.
+///
+/// Attributes are keyed by their qualified name, such as `xlink:href`. They are only changed
+/// through methods of `ElementData`, so that the parsed forms of the `id` and `class` attributes
+/// can be kept ready for selector matching.
+#[derive(Clone)]
+pub struct ElementData {
+    pub namespace: Namespace,
+    /// The local name of the element. Lowercase for HTML elements; SVG and MathML names keep
+    /// their case, as in `foreignObject`.
+    pub tag_name: String,
+    attributes: AttrMap,
+    id: Option<String>,
+    classes: HashSet<String>,
+}
+
+pub type AttrMap = HashMap<String, String>;
+
+/// The namespaces of elements and attributes that can appear in an HTML document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Namespace {
+    Html,
+    Svg,
+    MathMl,
+    XLink,
+    Xml,
+    Xmlns,
+}
+
+impl Namespace {
+    pub fn url(self) -> &'static str {
+        match self {
+            Namespace::Html => "http://www.w3.org/1999/xhtml",
+            Namespace::Svg => "http://www.w3.org/2000/svg",
+            Namespace::MathMl => "http://www.w3.org/1998/Math/MathML",
+            Namespace::XLink => "http://www.w3.org/1999/xlink",
+            Namespace::Xml => "http://www.w3.org/XML/1998/namespace",
+            Namespace::Xmlns => "http://www.w3.org/2000/xmlns/",
+        }
+    }
+}
+
+impl Node {
+    fn new(node_type: NodeType) -> Node {
+        Node {
+            node_type,
+            parent: None,
+            first_child: None,
+            last_child: None,
+            previous_sibling: None,
+            next_sibling: None,
+        }
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn first_child(&self) -> Option<NodeId> {
+        self.first_child
+    }
+
+    pub fn last_child(&self) -> Option<NodeId> {
+        self.last_child
+    }
+
+    pub fn previous_sibling(&self) -> Option<NodeId> {
+        self.previous_sibling
+    }
+
+    pub fn next_sibling(&self) -> Option<NodeId> {
+        self.next_sibling
+    }
+
+    /// The element data of an element node.
+    pub fn as_element(&self) -> Option<&ElementData> {
+        match self.node_type {
+            NodeType::Element(ref elem) => Some(elem),
+            _ => None,
+        }
+    }
+}
+
+/// A document tree, owning all of its nodes.
+///
+/// The document node itself is created along with the document and is always its
+/// [`root`](Document::root). Other nodes are created detached and then inserted into the tree.
+pub struct Document {
+    nodes: Vec<Node>,
+}
+
+impl Document {
+    pub fn new(quirks_mode: QuirksMode) -> Document {
+        Document { nodes: vec![Node::new(NodeType::Document(DocumentData { quirks_mode }))] }
+    }
+
+    /// The document node.
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// The first element child of the document node, normally `html`.
+    pub fn document_element(&self) -> Option<NodeId> {
+        self.children(self.root()).find(|&id| self.element(id).is_some())
+    }
+
+    pub fn quirks_mode(&self) -> QuirksMode {
+        match self.nodes[0].node_type {
+            NodeType::Document(ref data) => data.quirks_mode,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn set_quirks_mode(&mut self, quirks_mode: QuirksMode) {
+        if let NodeType::Document(ref mut data) = self.nodes[0].node_type {
+            data.quirks_mode = quirks_mode;
+        }
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0]
+    }
+
+    /// The element data of `id`, if it is an element.
+    pub fn element(&self, id: NodeId) -> Option<&ElementData> {
+        self.node(id).as_element()
+    }
+
+    /// Add a detached node to the document.
+    pub fn create_node(&mut self, node_type: NodeType) -> NodeId {
+        self.nodes.push(Node::new(node_type));
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Create a detached HTML element node with the given attributes.
+    pub fn create_element(&mut self, name: String, attrs: AttrMap) -> NodeId {
+        self.create_element_ns(Namespace::Html, name, attrs)
+    }
+
+    /// Create a detached element node in `namespace` with the given attributes.
+    pub fn create_element_ns(&mut self,
+                             namespace: Namespace,
+                             name: String,
+                             attrs: AttrMap)
+                             -> NodeId {
+        self.create_node(NodeType::Element(ElementData::new_ns(namespace, name, attrs)))
+    }
+
+    /// Create a detached text node.
+    pub fn create_text(&mut self, data: String) -> NodeId {
+        self.create_node(NodeType::Text(data))
+    }
+
+    /// Create a detached comment node.
+    pub fn create_comment(&mut self, data: String) -> NodeId {
+        self.create_node(NodeType::Comment(data))
+    }
+
+    /// Create a detached doctype node.
+    pub fn create_doctype(&mut self,
+                          name: String,
+                          public_id: Option<String>,
+                          system_id: Option<String>)
+                          -> NodeId {
+        self.create_node(NodeType::Doctype(DoctypeData {
+            name,
+            public_id,
+            system_id,
+        }))
+    }
+
+    /// Create a detached processing instruction node.
+    pub fn create_processing_instruction(&mut self, target: String, data: String) -> NodeId {
+        let data = ProcessingInstructionData { target, data };
+        self.create_node(NodeType::ProcessingInstruction(data))
+    }
+
+    /// Append `child` as the last child of `parent`, detaching it from its previous position
+    /// first.
+    pub fn append_child(&mut self, parent: NodeId, child: NodeId) {
+        self.insert_before(parent, child, None);
+    }
+
+    /// Insert `child` into `parent` just before `reference`, or as the last child if `reference`
+    /// is `None`. The child is detached from its previous position first.
+    ///
+    /// Panics if `reference` is not a child of `parent`, or if `child` is `parent` or one of its
+    /// ancestors.
+    pub fn insert_before(&mut self, parent: NodeId, child: NodeId, reference: Option<NodeId>) {
+        self.assert_not_ancestor(child, parent);
+        if reference == Some(child) {
+            return;
+        }
+        if let Some(reference) = reference {
+            assert_eq!(self.nodes[reference.0].parent,
+                       Some(parent),
+                       "reference is not a child of parent");
+        }
+        self.detach(child);
+        let previous = match reference {
+            Some(reference) => self.nodes[reference.0].previous_sibling,
+            None => self.nodes[parent.0].last_child,
+        };
+        match previous {
+            Some(previous) => self.nodes[previous.0].next_sibling = Some(child),
+            None => self.nodes[parent.0].first_child = Some(child),
+        }
+        match reference {
+            Some(reference) => self.nodes[reference.0].previous_sibling = Some(child),
+            None => self.nodes[parent.0].last_child = Some(child),
+        }
+        let node = &mut self.nodes[child.0];
+        node.parent = Some(parent);
+        node.previous_sibling = previous;
+        node.next_sibling = reference;
+    }
+
+    /// Remove `child` from `parent`. The removed node keeps its handle and its own children, and
+    /// can be inserted again later.
+    ///
+    /// Panics if `child` is not a child of `parent`.
+    pub fn remove_child(&mut self, parent: NodeId, child: NodeId) {
+        assert_eq!(self.nodes[child.0].parent, Some(parent), "node is not a child of parent");
+        self.detach(child);
+    }
+
+    /// Put `new_child` in the place of `old_child`, which is removed from `parent`.
+    ///
+    /// Panics if `old_child` is not a child of `parent`, or if `new_child` is `parent` or one of
+    /// its ancestors. The tree is left unchanged when it panics.
+    pub fn replace_child(&mut self, parent: NodeId, new_child: NodeId, old_child: NodeId) {
+        assert_eq!(self.nodes[old_child.0].parent, Some(parent), "node is not a child of parent");
+        self.assert_not_ancestor(new_child, parent);
+        if new_child == old_child {
+            return;
+        }
+        let reference = match self.nodes[old_child.0].next_sibling {
+            Some(next) if next == new_child => self.nodes[new_child.0].next_sibling,
+            next => next,
+        };
+        self.detach(old_child);
+        self.insert_before(parent, new_child, reference);
+    }
+
+    /// Panics if `node` is `parent` or one of its ancestors, since inserting it into `parent`
+    /// would make a cycle.
+    fn assert_not_ancestor(&self, node: NodeId, parent: NodeId) {
+        assert!(node != parent && !self.ancestors(parent).any(|id| id == node),
+                "cannot insert a node into itself");
+    }
+
+    /// Set an attribute of an element, replacing any previous value. Does nothing if `id` is not
+    /// an element.
+    pub fn set_attribute(&mut self, id: NodeId, name: &str, value: &str) {
+        if let NodeType::Element(ref mut elem) = self.nodes[id.0].node_type {
+            elem.set_attribute(name, value);
+        }
+    }
+
+    /// Remove an attribute of an element, returning its value if it was present.
+    pub fn remove_attribute(&mut self, id: NodeId, name: &str) -> Option<String> {
+        match self.nodes[id.0].node_type {
+            NodeType::Element(ref mut elem) => elem.remove_attribute(name),
+            _ => None,
+        }
+    }
+
+    /// Replace the data of a text, comment or processing instruction node. Other nodes are left
+    /// unchanged.
+    pub fn set_text(&mut self, id: NodeId, data: &str) {
+        match self.nodes[id.0].node_type {
+            NodeType::Text(ref mut text) | NodeType::Comment(ref mut text) => {
+                *text = data.to_string()
+            }
+            NodeType::ProcessingInstruction(ref mut pi) => pi.data = data.to_string(),
+            _ => {}
+        }
+    }
+
+    /// Create a detached copy of `id`. A deep copy also copies all of its descendants; a shallow
+    /// copy has no children.
+    pub fn clone_node(&mut self, id: NodeId, deep: bool) -> NodeId {
+        let node_type = self.nodes[id.0].node_type.clone();
+        let copy = self.create_node(node_type);
+        if deep {
+            let children: Vec<NodeId> = self.children(id).collect();
+            for child in children {
+                let child_copy = self.clone_node(child, true);
+                self.append_child(copy, child_copy);
+            }
+        }
+        copy
+    }
+
+    /// Unlink `id` from its parent and siblings. The node keeps its own children.
+    pub fn detach(&mut self, id: NodeId) {
+        let (parent, previous, next) = {
+            let node = &mut self.nodes[id.0];
+            let links = (node.parent, node.previous_sibling, node.next_sibling);
+            node.parent = None;
+            node.previous_sibling = None;
+            node.next_sibling = None;
+            links
+        };
+        let parent = match parent {
+            Some(parent) => parent,
+            None => return,
+        };
+        match previous {
+            Some(previous) => self.nodes[previous.0].next_sibling = next,
+            None => self.nodes[parent.0].first_child = next,
+        }
+        match next {
+            Some(next) => self.nodes[next.0].previous_sibling = previous,
+            None => self.nodes[parent.0].last_child = previous,
+        }
+    }
+
+    /// The nodes reached by starting at `start` and following the link selected by `step`.
+    fn walk<F>(&self, start: Option<NodeId>, step: F) -> impl Iterator<Item = NodeId> + '_
+        where F: Fn(&Node) -> Option<NodeId> + 'static
+    {
+        let mut current = start;
+        ::std::iter::from_fn(move || {
+            let id = current?;
+            current = step(self.node(id));
+            Some(id)
+        })
+    }
+
+    pub fn children(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.walk(self.node(id).first_child, |node| node.next_sibling)
+    }
+
+    /// The parent, grandparent and so on up to the root.
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.walk(self.node(id).parent, |node| node.parent)
+    }
+
+    /// The siblings after `id`, nearest first.
+    pub fn following_siblings(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.walk(self.node(id).next_sibling, |node| node.next_sibling)
+    }
+
+    /// The siblings before `id`, nearest first.
+    pub fn preceding_siblings(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.walk(self.node(id).previous_sibling, |node| node.previous_sibling)
+    }
+
+    /// All nodes below `id`, in document order.
+    pub fn descendants(&self, id: NodeId) -> Descendants<'_> {
+        Descendants {
+            document: self,
+            root: id,
+            next: self.node(id).first_child,
+        }
+    }
+
+    /// The elements below `id`, in document order.
+    pub fn descendant_elements(&self,
+                               id: NodeId)
+                               -> impl Iterator<Item = (NodeId, &ElementData)> {
+        self.descendants(id).filter_map(move |node| self.element(node).map(|elem| (node, elem)))
+    }
+
+    /// The first element in the document whose `id` attribute is `id`.
+    pub fn get_element_by_id(&self, id: &str) -> Option<NodeId> {
+        self.descendant_elements(self.root())
+            .find(|&(_, elem)| elem.id().is_some_and(|elem_id| elem_id == id))
+            .map(|(node, _)| node)
+    }
+
+    /// The elements below `node` with the given tag name, compared case-insensitively. The name
+    /// `*` matches every element.
+    pub fn get_elements_by_tag_name(&self, node: NodeId, name: &str) -> Vec<NodeId> {
+        self.descendant_elements(node)
+            .filter(|&(_, elem)| name == "*" || elem.tag_name.eq_ignore_ascii_case(name))
+            .map(|(node, _)| node)
+            .collect()
+    }
+
+    /// The elements below `node` that have every class in the whitespace-separated list `names`.
+    pub fn get_elements_by_class_name(&self, node: NodeId, names: &str) -> Vec<NodeId> {
+        let names: Vec<&str> = names.split_whitespace().collect();
+        if names.is_empty() {
+            return Vec::new();
+        }
+        self.descendant_elements(node)
+            .filter(|&(_, elem)| {
+                names.iter().all(|name| elem.has_class(name))
+            })
+            .map(|(node, _)| node)
+            .collect()
+    }
+
+    /// The markup for `id` and everything below it. See [`html::outer_html`] for pretty-printed
+    /// output.
+    pub fn outer_html(&self, id: NodeId) -> String {
+        html::outer_html(self, id, Default::default())
+    }
+
+    /// The markup for the children of `id`.
+    pub fn inner_html(&self, id: NodeId) -> String {
+        html::inner_html(self, id, Default::default())
+    }
+
+    /// The text of a text, comment or processing instruction node, or the concatenated text of
+    /// all text nodes below any other node.
+    pub fn text_content(&self, id: NodeId) -> String {
+        match self.node(id).node_type {
+            NodeType::Text(ref data) | NodeType::Comment(ref data) => data.clone(),
+            NodeType::ProcessingInstruction(ref pi) => pi.data.clone(),
+            _ => {
+                let mut text = String::new();
+                for node in self.descendants(id) {
+                    if let NodeType::Text(ref data) = self.node(node).node_type {
+                        text.push_str(data);
+                    }
+                }
+                text
+            }
+        }
+    }
+}
+
+/// Iterator over the descendants of a node in document order.
+pub struct Descendants<'a> {
+    document: &'a Document,
+    root: NodeId,
+    next: Option<NodeId>,
 }
-type AttrMap = HashMap<String, String>;
 
-fn text(data: String) -> Node {
-    Node {
-        children: Vec::new(),
-        node_type: NodeType::Text(data),
+impl<'a> Iterator for Descendants<'a> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.next?;
+        let node = self.document.node(id);
+        self.next = node.first_child.or_else(|| {
+            let mut current = id;
+            loop {
+                if current == self.root {
+                    return None;
+                }
+                let node = self.document.node(current);
+                if node.next_sibling.is_some() {
+                    return node.next_sibling;
+                }
+                current = node.parent?;
+            }
+        });
+        Some(id)
     }
 }
 
-fn elem(name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
-    Node {
-        children: children,
-        node_type: NodeType::Element(ElementData {
-            tag_name: name,
-            attributes: attrs,
-        }),
+impl ElementData {
+    /// HTML element data with the given attributes. Attribute names of HTML elements are compared
+    /// case-insensitively, so they are stored in lowercase.
+    pub fn new(tag_name: String, attributes: AttrMap) -> ElementData {
+        ElementData::new_ns(Namespace::Html, tag_name, attributes)
+    }
+
+    /// Element data in `namespace` with the given attributes.
+    pub fn new_ns(namespace: Namespace, tag_name: String, attributes: AttrMap) -> ElementData {
+        let mut elem = ElementData {
+            namespace,
+            tag_name,
+            attributes: HashMap::with_capacity(attributes.len()),
+            id: None,
+            classes: HashSet::new(),
+        };
+        for (name, value) in attributes {
+            elem.set_attribute(&name, &value);
+        }
+        elem
+    }
+
+    /// All attributes of the element, keyed by qualified name.
+    pub fn attributes(&self) -> &AttrMap {
+        &self.attributes
+    }
+
+    /// The value of the attribute with the qualified name `name`. Names are compared
+    /// case-insensitively on HTML elements.
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        match self.attributes.get(name) {
+            Some(value) => Some(value),
+            None if self.namespace == Namespace::Html &&
+                    name.bytes().any(|b| b.is_ascii_uppercase()) => {
+                self.attributes.get(&name.to_ascii_lowercase()).map(|value| &**value)
+            }
+            None => None,
+        }
+    }
+
+    /// The value of the attribute with the local name `local_name` in `namespace`, or in no
+    /// namespace if `namespace` is `None`.
+    ///
+    /// Only the attribute namespaces that the HTML parser assigns are recognized: `xlink:`,
+    /// `xml:` and `xmlns` attributes.
+    pub fn get_attribute_ns(&self, namespace: Option<Namespace>, local_name: &str) -> Option<&str> {
+        let name = match namespace {
+            None if local_name == "xmlns" => return None,
+            None => return self.get_attribute(local_name).filter(|_| !local_name.contains(':')),
+            Some(Namespace::XLink) => format!("xlink:{}", local_name),
+            Some(Namespace::Xml) => format!("xml:{}", local_name),
+            Some(Namespace::Xmlns) if local_name == "xmlns" => local_name.to_string(),
+            Some(Namespace::Xmlns) => format!("xmlns:{}", local_name),
+            Some(_) => return None,
+        };
+        self.attributes.get(&name).map(|value| &**value)
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.get_attribute(name).is_some()
+    }
+
+    /// Set the attribute `name`, replacing any previous value.
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        let name = self.normalize_attribute_name(name);
+        self.update_cache(&name, Some(value));
+        self.attributes.insert(name, value.to_string());
+    }
+
+    /// Remove the attribute `name`, returning its value if it was present.
+    pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        let name = self.normalize_attribute_name(name);
+        self.update_cache(&name, None);
+        self.attributes.remove(&name)
+    }
+
+    fn normalize_attribute_name(&self, name: &str) -> String {
+        match self.namespace {
+            Namespace::Html => name.to_ascii_lowercase(),
+            _ => name.to_string(),
+        }
+    }
+
+    fn update_cache(&mut self, name: &str, value: Option<&str>) {
+        match name {
+            "id" => self.id = value.map(|value| value.to_string()),
+            "class" => {
+                self.classes = value.into_iter()
+                    .flat_map(|value| value.split_ascii_whitespace())
+                    .map(|class| class.to_string())
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+
+    /// The value of the `id` attribute, if any.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// The whitespace-separated entries of the `class` attribute.
+    pub fn classes(&self) -> &HashSet<String> {
+        &self.classes
+    }
+
+    pub fn has_class(&self, name: &str) -> bool {
+        self.classes.contains(name)
+    }
+
+    /// The `data-*` attributes, keyed by the rest of the attribute name converted to camel case
+    /// as in the DOM's `dataset`: `data-user-id` becomes `userId`.
+    pub fn dataset(&self) -> HashMap<String, &str> {
+        self.attributes
+            .iter()
+            .filter_map(|(name, value)| {
+                let key = name.strip_prefix("data-")?;
+                let mut camel_case = String::with_capacity(key.len());
+                let mut chars = key.chars().peekable();
+                while let Some(c) = chars.next() {
+                    match chars.peek() {
+                        Some(&next) if c == '-' && next.is_ascii_lowercase() => {
+                            camel_case.push(next.to_ascii_uppercase());
+                            chars.next();
+                        }
+                        _ => camel_case.push(c),
+                    }
+                }
+                Some((camel_case, &**value))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use html::Parser;
+
+    /// Parse `source` as a complete HTML document.
+    fn parse(source: &str) -> Document {
+        Parser::parse(source.to_string())
+    }
+
+    fn by_id(document: &Document, id: &str) -> NodeId {
+        document.get_element_by_id(id).unwrap()
+    }
+
+    fn body(document: &Document) -> String {
+        let body = document.get_elements_by_tag_name(document.root(), "body")[0];
+        document.inner_html(body)
+    }
+
+    #[test]
+    fn insert_before_and_append() {
+        let mut document = parse("<p id=a></p><p id=b></p>");
+        let (a, b) = (by_id(&document, "a"), by_id(&document, "b"));
+        let parent = document.node(a).parent().unwrap();
+        let text = document.create_text("x".to_string());
+        document.insert_before(parent, text, Some(b));
+        assert_eq!(body(&document), "<p id=\"a\"></p>x<p id=\"b\"></p>");
+        // Inserting a node that is already in the tree moves it.
+        document.append_child(b, a);
+        assert_eq!(body(&document), "x<p id=\"b\"><p id=\"a\"></p></p>");
+        assert_eq!(document.node(a).parent(), Some(b));
+        assert_eq!(document.node(text).next_sibling(), Some(b));
+        // Inserting a node before itself leaves it where it is.
+        document.insert_before(parent, b, Some(b));
+        assert_eq!(body(&document), "x<p id=\"b\"><p id=\"a\"></p></p>");
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot insert a node into itself")]
+    fn insert_into_descendant_panics() {
+        let mut document = parse("<div id=a><p id=b></p></div>");
+        let (a, b) = (by_id(&document, "a"), by_id(&document, "b"));
+        document.append_child(b, a);
+    }
+
+    #[test]
+    fn remove_child_keeps_subtree() {
+        let mut document = parse("<div id=a><p>1</p></div><p id=b></p>");
+        let (a, b) = (by_id(&document, "a"), by_id(&document, "b"));
+        let parent = document.node(a).parent().unwrap();
+        document.remove_child(parent, a);
+        assert_eq!(body(&document), "<p id=\"b\"></p>");
+        assert_eq!(document.node(a).parent(), None);
+        assert_eq!(document.node(b).previous_sibling(), None);
+        assert_eq!(document.outer_html(a), "<div id=\"a\"><p>1</p></div>");
+        document.append_child(b, a);
+        assert_eq!(body(&document), "<p id=\"b\"><div id=\"a\"><p>1</p></div></p>");
+    }
+
+    #[test]
+    fn replace_child() {
+        let mut document = parse("<p id=a></p><p id=b></p><p id=c></p>");
+        let (a, b, c) = (by_id(&document, "a"), by_id(&document, "b"), by_id(&document, "c"));
+        let parent = document.node(a).parent().unwrap();
+        document.replace_child(parent, c, a);
+        assert_eq!(body(&document), "<p id=\"c\"></p><p id=\"b\"></p>");
+        // The new child can be the old child's next sibling.
+        document.replace_child(parent, b, c);
+        assert_eq!(body(&document), "<p id=\"b\"></p>");
+        document.replace_child(parent, b, b);
+        assert_eq!(body(&document), "<p id=\"b\"></p>");
+    }
+
+    #[test]
+    fn replace_child_with_ancestor_panics_without_changes() {
+        let mut document = parse("<div id=a><p id=b></p></div>");
+        let (a, b) = (by_id(&document, "a"), by_id(&document, "b"));
+        for &new_child in &[a, document.node(a).parent().unwrap()] {
+            let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+                document.replace_child(a, new_child, b)
+            }));
+            assert!(result.is_err());
+            assert_eq!(document.node(b).parent(), Some(a));
+            assert_eq!(body(&document), "<div id=\"a\"><p id=\"b\"></p></div>");
+        }
+    }
+
+    #[test]
+    fn set_attribute_and_text() {
+        let mut document = parse("<p id=a title=x>old<!--c--></p>");
+        let a = by_id(&document, "a");
+        document.set_attribute(a, "Title", "y");
+        assert_eq!(document.remove_attribute(a, "id"), Some("a".to_string()));
+        assert_eq!(document.remove_attribute(a, "id"), None);
+        let text = document.node(a).first_child().unwrap();
+        let comment = document.node(a).last_child().unwrap();
+        document.set_text(text, "new");
+        document.set_text(comment, "d");
+        // Setting the text of an element has no effect.
+        document.set_text(a, "ignored");
+        assert_eq!(body(&document), "<p title=\"y\">new<!--d--></p>");
+    }
+
+    #[test]
+    fn clone_node() {
+        let mut document = parse("<div id=a class=x><p>1</p></div>");
+        let a = by_id(&document, "a");
+        let shallow = document.clone_node(a, false);
+        let deep = document.clone_node(a, true);
+        assert_eq!(document.node(deep).parent(), None);
+        assert_eq!(document.outer_html(shallow), "<div class=\"x\" id=\"a\"></div>");
+        assert_eq!(document.outer_html(deep), "<div class=\"x\" id=\"a\"><p>1</p></div>");
+        // The copy is independent of the original.
+        let p = document.node(deep).first_child().unwrap();
+        document.set_text(document.node(p).first_child().unwrap(), "2");
+        assert_eq!(document.outer_html(a), "<div class=\"x\" id=\"a\"><p>1</p></div>");
+        assert!(document.element(deep).unwrap().has_class("x"));
+    }
+
+    #[test]
+    fn traversal() {
+        let document = parse("<div id=a><p id=b>1<i id=c>2</i></p><p id=d></p>3</div>");
+        let (a, b) = (by_id(&document, "a"), by_id(&document, "b"));
+        let (c, d) = (by_id(&document, "c"), by_id(&document, "d"));
+        let text = document.node(a).last_child().unwrap();
+        assert_eq!(document.children(a).collect::<Vec<_>>(), vec![b, d, text]);
+        let body = document.node(a).parent().unwrap();
+        let html = document.document_element().unwrap();
+        assert_eq!(document.ancestors(c).collect::<Vec<_>>(),
+                   vec![b, a, body, html, document.root()]);
+        assert_eq!(document.following_siblings(b).collect::<Vec<_>>(), vec![d, text]);
+        assert_eq!(document.preceding_siblings(text).collect::<Vec<_>>(), vec![d, b]);
+        assert_eq!(document.following_siblings(text).count(), 0);
+
+        let descendants: Vec<NodeId> = document.descendants(a).collect();
+        assert_eq!(descendants.len(), 6);
+        assert_eq!(descendants[..2], [b, document.node(b).first_child().unwrap()]);
+        assert_eq!(descendants[2], c);
+        assert_eq!(descendants[4..], [d, text]);
+        // The walk stops at the subtree root instead of continuing with its siblings.
+        assert_eq!(document.descendants(b).count(), 3);
+        assert_eq!(document.descendants(d).count(), 0);
+        let elements: Vec<NodeId> = document.descendant_elements(a).map(|(id, _)| id).collect();
+        assert_eq!(elements, vec![b, c, d]);
+    }
+
+    #[test]
+    fn element_queries() {
+        let document = parse("<div id=x class='a b'><P class=a>1</P><p id=x class='b  a c'>2</p>\
+                              <svg><foreignObject class=a></foreignObject></svg></div>");
+        let div = document.get_elements_by_tag_name(document.root(), "div")[0];
+        assert_eq!(document.get_element_by_id("x"), Some(div));
+        assert_eq!(document.get_element_by_id("y"), None);
+
+        let paragraphs = document.get_elements_by_tag_name(document.root(), "p");
+        assert_eq!(paragraphs.len(), 2);
+        assert_eq!(document.get_elements_by_tag_name(div, "P"), paragraphs);
+        assert_eq!(document.get_elements_by_tag_name(div, "foreignobject").len(), 1);
+        assert_eq!(document.get_elements_by_tag_name(div, "*").len(), 4);
+        assert_eq!(document.get_elements_by_tag_name(paragraphs[0], "p"), vec![]);
+
+        assert_eq!(document.get_elements_by_class_name(div, "a").len(), 3);
+        assert_eq!(document.get_elements_by_class_name(div, " b\ta "), vec![paragraphs[1]]);
+        assert_eq!(document.get_elements_by_class_name(document.root(), "a b").len(), 2);
+        assert_eq!(document.get_elements_by_class_name(div, "A"), vec![]);
+        assert_eq!(document.get_elements_by_class_name(div, "  "), vec![]);
+        assert_eq!(document.text_content(div), "12");
+    }
+
+    #[test]
+    fn handles_stay_valid_as_the_tree_changes() {
+        let mut document = Document::new(QuirksMode::NoQuirks);
+        let root = document.root();
+        let html = document.create_element("html".to_string(), AttrMap::new());
+        let text = document.create_text("t".to_string());
+        assert_eq!(document.node(html).parent(), None);
+        assert_eq!(document.document_element(), None);
+        document.append_child(root, html);
+        document.append_child(html, text);
+        assert_eq!(document.document_element(), Some(html));
+
+        let div = document.create_element("div".to_string(), AttrMap::new());
+        document.append_child(html, div);
+        document.append_child(div, text);
+        document.detach(div);
+        document.detach(div);
+        assert_eq!(document.node(html).first_child(), None);
+        assert_eq!(document.node(html).last_child(), None);
+        assert_eq!(document.node(text).parent(), Some(div));
+        assert_eq!(document.text_content(text), "t");
+        assert_eq!(document.outer_html(root), "<html></html>");
+
+        document.set_quirks_mode(QuirksMode::Quirks);
+        assert_eq!(document.quirks_mode(), QuirksMode::Quirks);
+    }
+
+    #[test]
+    fn id_and_classes_follow_attribute_changes() {
+        let mut attrs = AttrMap::new();
+        attrs.insert("ID".to_string(), "main".to_string());
+        attrs.insert("class".to_string(), " a\tb  a ".to_string());
+        let mut elem = ElementData::new("div".to_string(), attrs);
+        assert_eq!(elem.id(), Some("main"));
+        assert_eq!(elem.get_attribute("Id"), Some("main"));
+        assert_eq!(elem.classes().len(), 2);
+        assert!(elem.has_class("a") && elem.has_class("b") && !elem.has_class("A"));
+
+        elem.set_attribute("CLASS", "c");
+        assert!(elem.has_class("c") && !elem.has_class("a"));
+        elem.set_attribute("id", "");
+        assert_eq!(elem.id(), Some(""));
+        assert_eq!(elem.remove_attribute("class"), Some("c".to_string()));
+        assert!(elem.classes().is_empty());
+        elem.remove_attribute("id");
+        assert_eq!(elem.id(), None);
+        assert!(!elem.has_attribute("id"));
+    }
+
+    #[test]
+    fn foreign_attributes_keep_their_case() {
+        let mut attrs = AttrMap::new();
+        attrs.insert("viewBox".to_string(), "0 0 1 1".to_string());
+        attrs.insert("xlink:href".to_string(), "#a".to_string());
+        let elem = ElementData::new_ns(Namespace::Svg, "svg".to_string(), attrs);
+        assert_eq!(elem.get_attribute("viewBox"), Some("0 0 1 1"));
+        assert_eq!(elem.get_attribute("viewbox"), None);
+        assert_eq!(elem.get_attribute_ns(Some(Namespace::XLink), "href"), Some("#a"));
+        assert_eq!(elem.get_attribute_ns(None, "xlink:href"), None);
+        assert_eq!(elem.get_attribute_ns(None, "viewBox"), Some("0 0 1 1"));
+    }
+
+    #[test]
+    fn dataset() {
+        let document = parse("<p id=a data-user-id=7 data-x=1 data--y=2 data-a-B=3 datax=4>");
+        let dataset = document.element(by_id(&document, "a")).unwrap().dataset();
+        assert_eq!(dataset.len(), 4);
+        assert_eq!(dataset["userId"], "7");
+        assert_eq!(dataset["x"], "1");
+        assert_eq!(dataset["Y"], "2");
+        // Attribute names are lowercased by the parser.
+        assert_eq!(dataset["aB"], "3");
     }
 }
@@ -0,0 +1,73 @@
+//! Errors reported by the HTML and CSS parsers and when creating a `Viewport`.
+
+use std::error::Error;
+use std::fmt;
+
+/// A syntax error at a specific position in the source text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// Byte offset into the text the parser read: the source decoded to UTF-8, with CRLF pairs
+    /// and lone CRs normalized to LF (and, in CSS, NUL characters replaced with U+FFFD). It can
+    /// differ from the offset in the original source, so report `line` and `column` to users.
+    pub offset: usize,
+    /// One-based line number.
+    pub line: usize,
+    /// One-based column, counted in characters.
+    pub column: usize,
+    /// What the parser expected to find at this position.
+    pub expected: String,
+    /// The character actually found, or `None` at end of input.
+    pub found: Option<char>,
+}
+
+impl ParseError {
+    pub fn new(source: &str, offset: usize, expected: String) -> ParseError {
+        let before = &source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        ParseError {
+            offset,
+            line,
+            column: before[line_start..].chars().count() + 1,
+            expected,
+            found: source[offset..].chars().next(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: expected {}, found ", self.line, self.column, self.expected)?;
+        match self.found {
+            Some(c) => write!(f, "{:?}", c),
+            None => write!(f, "end of input"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// A viewport size that cannot be rendered.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ViewportError {
+    /// A width or height that is not a finite number of at least one pixel.
+    InvalidDimension(f32),
+    /// A viewport covering more than `MAX_VIEWPORT_PIXELS` pixels.
+    TooLarge { width: f32, height: f32 },
+}
+
+impl fmt::Display for ViewportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ViewportError::InvalidDimension(n) => {
+                write!(f, "{} is not a positive number of pixels", n)
+            }
+            ViewportError::TooLarge { width, height } => {
+                write!(f, "{}x{} is larger than the maximum of {} pixels",
+                       width, height, ::MAX_VIEWPORT_PIXELS)
+            }
+        }
+    }
+}
+
+impl Error for ViewportError {}
@@ -0,0 +1,219 @@
+//! Detection of the character encoding of an HTML byte stream.
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+
+/// How many bytes at the start of a document are searched for a `<meta>` charset declaration.
+pub const PRESCAN_LENGTH: usize = 1024;
+
+/// Determine the encoding of a document from its first bytes.
+///
+/// A byte order mark takes precedence, followed by a `<meta charset>` or
+/// `<meta http-equiv="Content-Type">` declaration. Otherwise the document is assumed to be
+/// UTF-8 if it decodes as such, and Windows-1252 (the usual superset of Latin-1) if not.
+/// Returns the encoding and the length of the byte order mark to skip.
+///
+/// `last` says whether `bytes` is the whole document, or only its start.
+pub fn sniff(bytes: &[u8], last: bool) -> (&'static Encoding, usize) {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        return (encoding, bom_length);
+    }
+    let head = &bytes[..bytes.len().min(PRESCAN_LENGTH)];
+    if let Some(encoding) = prescan(head) {
+        return (encoding, 0);
+    }
+    let truncated = head.len() < bytes.len() || !last;
+    if looks_like_utf8(head, truncated) {
+        (UTF_8, 0)
+    } else {
+        (WINDOWS_1252, 0)
+    }
+}
+
+/// Whether `bytes` is valid UTF-8. If `truncated` is set, more input follows, so a sequence cut
+/// off at the end is allowed.
+fn looks_like_utf8(bytes: &[u8], truncated: bool) -> bool {
+    match ::std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(e) => truncated && e.error_len().is_none(),
+    }
+}
+
+/// A simplified version of the HTML5 "prescan a byte stream to determine its encoding"
+/// algorithm: look for a `<meta>` tag declaring a charset, skipping comments and other tags.
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let rest = &bytes[pos..];
+        if rest.starts_with(b"<!--") {
+            pos = find(&rest[4..], b"-->").map(|end| pos + 4 + end + 3)?;
+        } else if starts_with_ignore_case(rest, b"<meta") &&
+                  rest.get(5).is_some_and(|&b| b.is_ascii_whitespace() || b == b'/') {
+            pos += 5;
+            if let Some(encoding) = meta_charset(bytes, &mut pos) {
+                return Some(match encoding {
+                    e if e == UTF_16BE || e == UTF_16LE => UTF_8,
+                    e if e == X_USER_DEFINED => WINDOWS_1252,
+                    e => e,
+                });
+            }
+        } else if rest.starts_with(b"<") &&
+                  rest.get(1).is_some_and(|&b| b.is_ascii_alphabetic() || b"/!?".contains(&b)) {
+            pos = find(rest, b">").map(|end| pos + end + 1)?;
+        } else {
+            pos += 1;
+        }
+    }
+    None
+}
+
+/// Read the attributes of a `<meta>` tag and return the encoding it declares, if any.
+fn meta_charset(bytes: &[u8], pos: &mut usize) -> Option<&'static Encoding> {
+    let mut charset = None;
+    let mut content_type = false;
+    let mut content_charset = None;
+    while let Some((name, value)) = next_attribute(bytes, pos) {
+        match &*name {
+            "charset" if charset.is_none() => charset = Some(value),
+            "http-equiv" => content_type |= value.eq_ignore_ascii_case("content-type"),
+            "content" if content_charset.is_none() => {
+                content_charset = charset_from_content(&value)
+            }
+            _ => {}
+        }
+    }
+    let label = match (charset, content_type, content_charset) {
+        (Some(label), _, _) => label,
+        (None, true, Some(label)) => label,
+        _ => return None,
+    };
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+/// Parse one attribute of a tag, returning `None` at the end of the tag.
+fn next_attribute(bytes: &[u8], pos: &mut usize) -> Option<(String, String)> {
+    while *pos < bytes.len() && (bytes[*pos].is_ascii_whitespace() || bytes[*pos] == b'/') {
+        *pos += 1;
+    }
+    if *pos >= bytes.len() || bytes[*pos] == b'>' {
+        return None;
+    }
+
+    let name_start = *pos;
+    *pos += 1;
+    while *pos < bytes.len() && !matches!(bytes[*pos], b'=' | b'/' | b'>') &&
+          !bytes[*pos].is_ascii_whitespace() {
+        *pos += 1;
+    }
+    let name = String::from_utf8_lossy(&bytes[name_start..*pos]).to_ascii_lowercase();
+
+    skip_whitespace(bytes, pos);
+    if bytes.get(*pos) != Some(&b'=') {
+        return Some((name, String::new()));
+    }
+    *pos += 1;
+    skip_whitespace(bytes, pos);
+
+    let value = match bytes.get(*pos) {
+        Some(&quote) if quote == b'"' || quote == b'\'' => {
+            let start = *pos + 1;
+            let end = bytes[start..].iter().position(|&b| b == quote).map(|i| start + i)?;
+            *pos = end + 1;
+            &bytes[start..end]
+        }
+        _ => {
+            let start = *pos;
+            while *pos < bytes.len() && bytes[*pos] != b'>' && !bytes[*pos].is_ascii_whitespace() {
+                *pos += 1;
+            }
+            &bytes[start..*pos]
+        }
+    };
+    Some((name, String::from_utf8_lossy(value).into_owned()))
+}
+
+/// Extract the charset from a `content` attribute such as `text/html; charset=utf-8`.
+fn charset_from_content(content: &str) -> Option<String> {
+    let lower = content.to_ascii_lowercase();
+    let start = lower.find("charset")? + "charset".len();
+    let rest = lower[start..].trim_start();
+    let rest = rest.strip_prefix('=')?.trim_start();
+    let value = match rest.chars().next()? {
+        quote @ '"' | quote @ '\'' => rest[1..].split(quote).next()?,
+        _ => rest.split(|c: char| c == ';' || c.is_whitespace()).next()?,
+    };
+    Some(value.to_string())
+}
+
+fn skip_whitespace(bytes: &[u8], pos: &mut usize) {
+    while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+        *pos += 1;
+    }
+}
+
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.get(..prefix.len()).is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{UTF_16LE, UTF_8, WINDOWS_1252};
+
+    use super::{sniff, PRESCAN_LENGTH};
+
+    #[test]
+    fn byte_order_mark_wins() {
+        assert_eq!(sniff(b"\xef\xbb\xbf<meta charset=latin1>", true), (UTF_8, 3));
+        assert_eq!(sniff(b"\xff\xfe<\0p\0", true), (UTF_16LE, 2));
+    }
+
+    #[test]
+    fn meta_charset() {
+        let (encoding, _) = sniff(b"<!doctype html><meta charset=\"windows-1252\">", true);
+        assert_eq!(encoding, WINDOWS_1252);
+        let (encoding, _) = sniff(b"<META CHARSET = 'ISO-8859-1'>", true);
+        assert_eq!(encoding, WINDOWS_1252);
+    }
+
+    #[test]
+    fn meta_http_equiv() {
+        let source = b"<meta http-equiv=Content-Type content=\"text/html; charset=latin1\">";
+        assert_eq!(sniff(source, true).0, WINDOWS_1252);
+        // Without http-equiv, the content attribute is ignored.
+        let source = "<meta content=\"text/html; charset=latin1\">é".as_bytes();
+        assert_eq!(sniff(source, true).0, UTF_8);
+    }
+
+    #[test]
+    fn meta_in_comment_or_attribute_is_ignored() {
+        assert_eq!(sniff(b"<!-- <meta charset=latin1> -->", true).0, UTF_8);
+        assert_eq!(sniff(b"<p title='<meta charset=latin1>'>", true).0, UTF_8);
+    }
+
+    #[test]
+    fn utf_16_and_x_user_defined_declarations_are_replaced() {
+        assert_eq!(sniff(b"<meta charset=utf-16le>", true).0, UTF_8);
+        assert_eq!(sniff(b"<meta charset=x-user-defined>", true).0, WINDOWS_1252);
+    }
+
+    #[test]
+    fn falls_back_to_windows_1252_for_invalid_utf_8() {
+        assert_eq!(sniff("<p>caf\u{e9}".as_bytes(), true).0, UTF_8);
+        assert_eq!(sniff(b"<p>caf\xe9 au lait", true).0, WINDOWS_1252);
+    }
+
+    #[test]
+    fn cut_off_sequence_is_only_allowed_if_more_input_follows() {
+        assert_eq!(sniff(b"<p>\xe9", true).0, WINDOWS_1252);
+        assert_eq!(sniff(b"<p>\xc3", false).0, UTF_8);
+
+        let mut source = vec![b'a'; PRESCAN_LENGTH - 1];
+        source.extend_from_slice("\u{e9}".as_bytes());
+        assert_eq!(sniff(&source, true).0, UTF_8);
+        source.truncate(PRESCAN_LENGTH);
+        assert_eq!(sniff(&source, true).0, WINDOWS_1252);
+    }
+}
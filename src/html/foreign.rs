@@ -0,0 +1,159 @@
+//! Adjustments for SVG and MathML elements embedded in HTML.
+//!
+//! The tokenizer lowercases tag and attribute names, but SVG and MathML are case-sensitive. The
+//! tables here restore the case of the names that have any uppercase letters.
+
+use super::tokenizer::Tag;
+
+/// Start tags that end foreign content and are handled as HTML instead.
+pub const BREAKOUT_ELEMENTS: &[&str] = &["b", "big", "blockquote", "body", "br", "center", "code",
+                                         "dd", "div", "dl", "dt", "em", "embed", "h1", "h2", "h3",
+                                         "h4", "h5", "h6", "head", "hr", "i", "img", "li",
+                                         "listing", "menu", "meta", "nobr", "ol", "p", "pre",
+                                         "ruby", "s", "small", "span", "strong", "strike", "sub",
+                                         "sup", "table", "tt", "u", "ul", "var"];
+
+/// MathML elements whose text content is HTML text.
+pub const MATHML_TEXT_INTEGRATION_POINTS: &[&str] = &["mi", "mo", "mn", "ms", "mtext"];
+
+/// SVG elements whose content is HTML.
+pub const SVG_HTML_INTEGRATION_POINTS: &[&str] = &["foreignObject", "desc", "title"];
+
+const SVG_TAG_NAMES: &[(&str, &str)] = &[
+    ("altglyph", "altGlyph"),
+    ("altglyphdef", "altGlyphDef"),
+    ("altglyphitem", "altGlyphItem"),
+    ("animatecolor", "animateColor"),
+    ("animatemotion", "animateMotion"),
+    ("animatetransform", "animateTransform"),
+    ("clippath", "clipPath"),
+    ("feblend", "feBlend"),
+    ("fecolormatrix", "feColorMatrix"),
+    ("fecomponenttransfer", "feComponentTransfer"),
+    ("fecomposite", "feComposite"),
+    ("feconvolvematrix", "feConvolveMatrix"),
+    ("fediffuselighting", "feDiffuseLighting"),
+    ("fedisplacementmap", "feDisplacementMap"),
+    ("fedistantlight", "feDistantLight"),
+    ("fedropshadow", "feDropShadow"),
+    ("feflood", "feFlood"),
+    ("fefunca", "feFuncA"),
+    ("fefuncb", "feFuncB"),
+    ("fefuncg", "feFuncG"),
+    ("fefuncr", "feFuncR"),
+    ("fegaussianblur", "feGaussianBlur"),
+    ("feimage", "feImage"),
+    ("femerge", "feMerge"),
+    ("femergenode", "feMergeNode"),
+    ("femorphology", "feMorphology"),
+    ("feoffset", "feOffset"),
+    ("fepointlight", "fePointLight"),
+    ("fespecularlighting", "feSpecularLighting"),
+    ("fespotlight", "feSpotLight"),
+    ("fetile", "feTile"),
+    ("feturbulence", "feTurbulence"),
+    ("foreignobject", "foreignObject"),
+    ("glyphref", "glyphRef"),
+    ("lineargradient", "linearGradient"),
+    ("radialgradient", "radialGradient"),
+    ("textpath", "textPath"),
+];
+
+const SVG_ATTRIBUTE_NAMES: &[(&str, &str)] = &[
+    ("attributename", "attributeName"),
+    ("attributetype", "attributeType"),
+    ("basefrequency", "baseFrequency"),
+    ("baseprofile", "baseProfile"),
+    ("calcmode", "calcMode"),
+    ("clippathunits", "clipPathUnits"),
+    ("diffuseconstant", "diffuseConstant"),
+    ("edgemode", "edgeMode"),
+    ("filterunits", "filterUnits"),
+    ("glyphref", "glyphRef"),
+    ("gradienttransform", "gradientTransform"),
+    ("gradientunits", "gradientUnits"),
+    ("kernelmatrix", "kernelMatrix"),
+    ("kernelunitlength", "kernelUnitLength"),
+    ("keypoints", "keyPoints"),
+    ("keysplines", "keySplines"),
+    ("keytimes", "keyTimes"),
+    ("lengthadjust", "lengthAdjust"),
+    ("limitingconeangle", "limitingConeAngle"),
+    ("markerheight", "markerHeight"),
+    ("markerunits", "markerUnits"),
+    ("markerwidth", "markerWidth"),
+    ("maskcontentunits", "maskContentUnits"),
+    ("maskunits", "maskUnits"),
+    ("numoctaves", "numOctaves"),
+    ("pathlength", "pathLength"),
+    ("patterncontentunits", "patternContentUnits"),
+    ("patterntransform", "patternTransform"),
+    ("patternunits", "patternUnits"),
+    ("pointsatx", "pointsAtX"),
+    ("pointsaty", "pointsAtY"),
+    ("pointsatz", "pointsAtZ"),
+    ("preservealpha", "preserveAlpha"),
+    ("preserveaspectratio", "preserveAspectRatio"),
+    ("primitiveunits", "primitiveUnits"),
+    ("refx", "refX"),
+    ("refy", "refY"),
+    ("repeatcount", "repeatCount"),
+    ("repeatdur", "repeatDur"),
+    ("requiredextensions", "requiredExtensions"),
+    ("requiredfeatures", "requiredFeatures"),
+    ("specularconstant", "specularConstant"),
+    ("specularexponent", "specularExponent"),
+    ("spreadmethod", "spreadMethod"),
+    ("startoffset", "startOffset"),
+    ("stddeviation", "stdDeviation"),
+    ("stitchtiles", "stitchTiles"),
+    ("surfacescale", "surfaceScale"),
+    ("systemlanguage", "systemLanguage"),
+    ("tablevalues", "tableValues"),
+    ("targetx", "targetX"),
+    ("targety", "targetY"),
+    ("textlength", "textLength"),
+    ("viewbox", "viewBox"),
+    ("viewtarget", "viewTarget"),
+    ("xchannelselector", "xChannelSelector"),
+    ("ychannelselector", "yChannelSelector"),
+    ("zoomandpan", "zoomAndPan"),
+];
+
+const MATHML_ATTRIBUTE_NAMES: &[(&str, &str)] = &[("definitionurl", "definitionURL")];
+
+fn lookup(table: &[(&str, &'static str)], name: &str) -> Option<&'static str> {
+    table.iter().find(|&&(lowercase, _)| lowercase == name).map(|&(_, adjusted)| adjusted)
+}
+
+/// Whether a start tag leaves foreign content.
+pub fn is_breakout(tag: &Tag) -> bool {
+    BREAKOUT_ELEMENTS.contains(&&*tag.name) ||
+    tag.name == "font" &&
+    ["color", "face", "size"].iter().any(|&name| tag.attributes.contains_key(name))
+}
+
+/// Restore the case of an SVG tag name.
+pub fn adjust_svg_tag_name(tag: &mut Tag) {
+    if let Some(name) = lookup(SVG_TAG_NAMES, &tag.name) {
+        tag.name = name.to_string();
+    }
+}
+
+/// Restore the case of SVG attribute names.
+pub fn adjust_svg_attributes(tag: &mut Tag) {
+    adjust_attributes(tag, SVG_ATTRIBUTE_NAMES);
+}
+
+/// Restore the case of MathML attribute names.
+pub fn adjust_mathml_attributes(tag: &mut Tag) {
+    adjust_attributes(tag, MATHML_ATTRIBUTE_NAMES);
+}
+
+fn adjust_attributes(tag: &mut Tag, table: &[(&str, &'static str)]) {
+    for &(lowercase, adjusted) in table {
+        if let Some(value) = tag.attributes.remove(lowercase) {
+            tag.attributes.insert(adjusted.to_string(), value);
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! An HTML parser.
+//!
+//! Parsing happens in two stages, following the structure of the HTML5 parsing algorithm: a
+//! [`Tokenizer`] splits the source into tags and text, and a [`TreeBuilder`] assembles those
+//! tokens into a [`dom::Document`] tree, recovering from missing end tags and misnested markup the
+//! way browsers do.
+//!
+//! A [`Parser`] can be fed the document in chunks, either as text or as bytes in an encoding
+//! that it detects itself. Parsing never fails: syntax errors are recovered from and can be
+//! collected as warnings. [`outer_html`] and [`inner_html`] turn a tree back into markup.
+
+use std::io::{self, Read};
+use std::mem;
+
+use encoding_rs::{CoderResult, Decoder, Encoding};
+
+use dom;
+use error::ParseError;
+
+pub use self::serializer::{inner_html, outer_html, SerializeOptions};
+pub use self::tokenizer::{Tag, TextContent, Token, Tokenizer};
+pub use self::tree_builder::TreeBuilder;
+
+mod encoding;
+mod entities;
+mod foreign;
+mod serializer;
+mod tokenizer;
+mod tree_builder;
+
+/// Parses HTML source into a [`dom::Document`].
+pub struct Parser {
+    tokenizer: Tokenizer,
+    builder: TreeBuilder,
+    encoding: Option<&'static Encoding>,
+    decoder: Option<Decoder>,
+    /// Bytes held back until there are enough to detect the encoding.
+    undecoded: Vec<u8>,
+    warnings: Vec<ParseError>,
+}
+
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser::new()
+    }
+}
+
+impl Parser {
+    /// A parser that is fed its input incrementally with [`feed_bytes`](Parser::feed_bytes) or
+    /// [`feed_str`](Parser::feed_str).
+    pub fn new() -> Parser {
+        Parser {
+            tokenizer: Tokenizer::streaming(),
+            builder: TreeBuilder::new(),
+            encoding: None,
+            decoder: None,
+            undecoded: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Feed the next chunk of an encoded document.
+    ///
+    /// The encoding is detected from a byte order mark or a `<meta charset>` declaration in the
+    /// first kilobyte, so nothing is tokenized until that much input has arrived.
+    pub fn feed_bytes(&mut self, bytes: &[u8]) {
+        if self.decoder.is_some() {
+            self.decode(bytes, false);
+        } else {
+            self.undecoded.extend_from_slice(bytes);
+            if self.undecoded.len() >= encoding::PRESCAN_LENGTH {
+                self.start_decoding(false);
+            }
+        }
+    }
+
+    /// Feed the next chunk of an already decoded document.
+    pub fn feed_str(&mut self, text: &str) {
+        self.tokenizer.feed(text);
+        self.process_tokens();
+    }
+
+    /// The name of the encoding detected for input fed as bytes, once it is known.
+    pub fn encoding(&self) -> Option<&'static str> {
+        self.encoding.map(Encoding::name)
+    }
+
+    /// Process the remaining input and return the document.
+    pub fn finish(self) -> dom::Document {
+        self.finish_with_warnings().0
+    }
+
+    /// Process the remaining input and return the document, along with a warning for each syntax
+    /// error that was recovered from, such as a tag cut off by the end of the input.
+    pub fn finish_with_warnings(mut self) -> (dom::Document, Vec<ParseError>) {
+        if self.decoder.is_some() {
+            self.decode(&[], true);
+        } else if !self.undecoded.is_empty() {
+            self.start_decoding(true);
+        }
+        self.tokenizer.end();
+        self.process_tokens();
+        (self.builder.finish(), self.warnings)
+    }
+
+    /// Parse a complete document from a byte slice, detecting its encoding.
+    pub fn parse_bytes(bytes: &[u8]) -> dom::Document {
+        let mut parser = Parser::new();
+        parser.feed_bytes(bytes);
+        parser.finish()
+    }
+
+    /// Parse a document from a reader, detecting its encoding and tokenizing it as it arrives.
+    pub fn parse_reader<R: Read>(reader: R) -> io::Result<dom::Document> {
+        Parser::parse_reader_with_warnings(reader).map(|(document, _)| document)
+    }
+
+    /// Like [`parse_reader`](Parser::parse_reader), but also return the warnings from
+    /// [`finish_with_warnings`](Parser::finish_with_warnings).
+    pub fn parse_reader_with_warnings<R: Read>(mut reader: R)
+                                               -> io::Result<(dom::Document, Vec<ParseError>)> {
+        let mut parser = Parser::new();
+        let mut buffer = [0; 8192];
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            parser.feed_bytes(&buffer[..read]);
+        }
+        Ok(parser.finish_with_warnings())
+    }
+
+    fn start_decoding(&mut self, last: bool) {
+        let bytes = mem::take(&mut self.undecoded);
+        let (encoding, bom_length) = encoding::sniff(&bytes, last);
+        self.encoding = Some(encoding);
+        self.decoder = Some(encoding.new_decoder_without_bom_handling());
+        self.decode(&bytes[bom_length..], last)
+    }
+
+    fn decode(&mut self, bytes: &[u8], last: bool) {
+        {
+            let decoder = self.decoder.as_mut().unwrap();
+            let capacity = decoder.max_utf8_buffer_length(bytes.len()).unwrap_or(bytes.len() * 3);
+            let mut text = String::with_capacity(capacity);
+            let (result, _, _) = decoder.decode_to_string(bytes, &mut text, last);
+            debug_assert_eq!(result, CoderResult::InputEmpty);
+            self.tokenizer.feed(&text);
+        }
+        self.process_tokens();
+    }
+
+    fn process_tokens(&mut self) {
+        while let Some(token) = self.tokenizer.next_token() {
+            if let Some((kind, name)) = self.builder.process_token(token) {
+                self.tokenizer.set_text_content(kind, &name);
+            }
+        }
+        self.warnings.extend(self.tokenizer.take_warnings());
+    }
+
+    /// Parse an HTML document.
+    ///
+    /// The children of the document node are its doctype, any comments outside the `html`
+    /// element, and the `html` element itself. Missing `html`, `head` and `body` elements are
+    /// created as needed. The doctype decides the document's [`dom::QuirksMode`].
+    pub fn parse(source: String) -> dom::Document {
+        Parser::parse_with_warnings(source).0
+    }
+
+    /// Parse an HTML document, also returning a warning for each syntax error that was recovered
+    /// from.
+    pub fn parse_with_warnings(source: String) -> (dom::Document, Vec<ParseError>) {
+        let mut parser = Parser::new();
+        parser.tokenizer = Tokenizer::new(source);
+        parser.finish_with_warnings()
+    }
+}
+
+/// The contents of every `<style>` element in a document, in document order.
+pub fn embedded_styles(document: &dom::Document) -> Vec<String> {
+    document.get_elements_by_tag_name(document.root(), "style")
+        .into_iter()
+        .map(|id| document.text_content(id))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Parser;
+
+    fn body(document: &::dom::Document) -> String {
+        let body = document.get_elements_by_tag_name(document.root(), "body")[0];
+        document.inner_html(body)
+    }
+
+    /// Feed `bytes` to a parser `chunk_size` bytes at a time.
+    fn parse_in_chunks(bytes: &[u8], chunk_size: usize) -> (Option<&'static str>, String) {
+        let mut parser = Parser::new();
+        for chunk in bytes.chunks(chunk_size) {
+            parser.feed_bytes(chunk);
+        }
+        let encoding = parser.encoding();
+        (encoding, body(&parser.finish()))
+    }
+
+    #[test]
+    fn bytes_split_inside_multibyte_sequences() {
+        // Long enough that decoding starts before the end, with characters that straddle the end
+        // of the prescan window.
+        let text = "caf\u{e9} \u{65e5}\u{672c} \u{1f600} ".repeat(100);
+        let source = format!("<p>{}</p>", text);
+        for chunk_size in 1..8 {
+            let (encoding, html) = parse_in_chunks(source.as_bytes(), chunk_size);
+            assert_eq!(encoding, Some("UTF-8"));
+            assert_eq!(html, format!("<p>{}</p>", text));
+        }
+    }
+
+    #[test]
+    fn bytes_in_a_declared_encoding() {
+        let mut source = b"<meta charset=windows-1252><p>caf\xe9 \x80".to_vec();
+        source.extend(vec![b' '; 2000]);
+        let (encoding, html) = parse_in_chunks(&source, 3);
+        assert_eq!(encoding, Some("windows-1252"));
+        assert!(html.starts_with("<p>caf\u{e9} \u{20ac} "));
+    }
+
+    #[test]
+    fn short_document_in_windows_1252() {
+        let document = Parser::parse_bytes(b"<p>\xe9");
+        assert_eq!(body(&document), "<p>\u{e9}</p>");
+    }
+
+    #[test]
+    fn line_breaks_split_across_chunks() {
+        let mut parser = Parser::new();
+        for chunk in ["<pre>a\r", "\nb\r", "c\r", "", "\n", "d</pre>"] {
+            parser.feed_str(chunk);
+        }
+        assert_eq!(body(&parser.finish()), "<pre>a\nb\nc\nd</pre>");
+    }
+
+    #[test]
+    fn text_split_across_chunks() {
+        let source = "<title>a &amp; b</title><script>if (a</b) x = '</scr' + 'ipt>';</script>\
+                      <p>x &amp y &notit; <3 &#x41;</p><textarea>&lt;/textarea&gt;</textarea>";
+        let document = Parser::parse(source.to_string());
+        let whole = document.outer_html(document.root());
+        for chunk_size in 1..6 {
+            let mut parser = Parser::new();
+            let chars: Vec<char> = source.chars().collect();
+            for chunk in chars.chunks(chunk_size) {
+                parser.feed_str(&chunk.iter().collect::<String>());
+            }
+            let document = parser.finish();
+            assert_eq!(document.outer_html(document.root()), whole);
+        }
+    }
+
+    #[test]
+    fn tag_cut_off_by_end_of_input_is_dropped() {
+        for source in ["<p>kept</p><div class=\"x", "<p>kept</p><div class=", "<p>kept</p><br/",
+                       "<p>kept</p></p"] {
+            let (document, warnings) = Parser::parse_with_warnings(source.to_string());
+            assert_eq!(body(&document), "<p>kept</p>", "{}", source);
+            assert_eq!(warnings.len(), 1, "{}", source);
+            assert_eq!(warnings[0].offset, source.len());
+            assert_eq!(warnings[0].found, None);
+        }
+        let (_, warnings) = Parser::parse_with_warnings("<p>\n<a href='x".to_string());
+        assert_eq!((warnings[0].line, warnings[0].column), (2, 11));
+    }
+
+    #[test]
+    fn tag_split_across_chunks_is_kept() {
+        let mut parser = Parser::new();
+        for chunk in ["<p>a</p><a hr", "ef=\"x", "\">b</a"] {
+            parser.feed_str(chunk);
+        }
+        parser.feed_str(">");
+        let (document, warnings) = parser.finish_with_warnings();
+        assert_eq!(body(&document), "<p>a</p><a href=\"x\">b</a>");
+        assert!(warnings.is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+//! Serialization of document trees back into markup, following the HTML fragment serialization
+//! algorithm.
+
+use dom::{Document, ElementData, Namespace, NodeId, NodeType};
+use super::tree_builder::VOID_ELEMENTS;
+
+/// Elements whose text content is written out without escaping.
+const RAW_TEXT_ELEMENTS: &[&str] = &["iframe", "noembed", "noframes", "noscript", "plaintext",
+                                     "script", "style", "xmp"];
+
+/// Elements whose leading newline is dropped by the parser.
+const NEWLINE_ELEMENTS: &[&str] = &["listing", "pre", "textarea"];
+
+/// How a tree is written out as markup.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SerializeOptions {
+    /// If set, put each child of an element on its own line, indented by this many spaces per
+    /// level. Whitespace-only text is dropped and other text is trimmed, except inside `pre`,
+    /// `textarea`, `listing` and raw text elements. If unset, the tree is written out exactly.
+    pub indent: Option<usize>,
+}
+
+/// The markup for `id` and everything below it.
+///
+/// Attributes are written in name order, so the output only depends on the tree.
+pub fn outer_html(document: &Document, id: NodeId, options: SerializeOptions) -> String {
+    let mut serializer = Serializer {
+        document,
+        options,
+        output: String::new(),
+    };
+    serializer.node(id, 0);
+    serializer.output
+}
+
+/// The markup for the children of `id`.
+pub fn inner_html(document: &Document, id: NodeId, options: SerializeOptions) -> String {
+    let mut serializer = Serializer {
+        document,
+        options,
+        output: String::new(),
+    };
+    serializer.children(id, 0, true);
+    serializer.output
+}
+
+struct Serializer<'a> {
+    document: &'a Document,
+    options: SerializeOptions,
+    output: String,
+}
+
+impl<'a> Serializer<'a> {
+    fn node(&mut self, id: NodeId, depth: usize) {
+        let document = self.document;
+        match document.node(id).node_type {
+            NodeType::Document(_) => self.children(id, depth, true),
+            NodeType::Doctype(ref doctype) => {
+                self.output.push_str("<!DOCTYPE ");
+                self.output.push_str(&doctype.name);
+                self.output.push('>');
+            }
+            NodeType::Text(ref text) => {
+                let raw = document.node(id)
+                    .parent()
+                    .and_then(|parent| document.element(parent))
+                    .is_some_and(|parent| is_html(parent, RAW_TEXT_ELEMENTS));
+                if raw {
+                    self.output.push_str(text);
+                } else {
+                    escape(text, false, &mut self.output);
+                }
+            }
+            NodeType::Comment(ref data) => {
+                self.output.push_str("<!--");
+                self.output.push_str(data);
+                self.output.push_str("-->");
+            }
+            NodeType::ProcessingInstruction(ref pi) => {
+                self.output.push_str("<?");
+                self.output.push_str(&pi.target);
+                self.output.push(' ');
+                self.output.push_str(&pi.data);
+                self.output.push('>');
+            }
+            NodeType::Element(ref elem) => {
+                self.output.push('<');
+                self.output.push_str(&elem.tag_name);
+                let mut attributes: Vec<_> = elem.attributes().iter().collect();
+                attributes.sort();
+                for (name, value) in attributes {
+                    self.output.push(' ');
+                    self.output.push_str(name);
+                    self.output.push_str("=\"");
+                    escape(value, true, &mut self.output);
+                    self.output.push('"');
+                }
+                self.output.push('>');
+                if is_html(elem, VOID_ELEMENTS) {
+                    return;
+                }
+                if is_html(elem, NEWLINE_ELEMENTS) {
+                    let leading_newline = document.node(id)
+                        .first_child()
+                        .is_some_and(|child| match document.node(child).node_type {
+                            NodeType::Text(ref text) => text.starts_with('\n'),
+                            _ => false,
+                        });
+                    if leading_newline {
+                        self.output.push('\n');
+                    }
+                }
+                self.children(id, depth + 1, false);
+                self.output.push_str("</");
+                self.output.push_str(&elem.tag_name);
+                self.output.push('>');
+            }
+        }
+    }
+
+    /// Write the children of `id`. When pretty-printing, children at the top level of the
+    /// output start at the first column; those of an element go on new lines below it.
+    fn children(&mut self, id: NodeId, depth: usize, top_level: bool) {
+        let document = self.document;
+        let indent = match self.options.indent {
+            Some(indent) if !preserves_whitespace(document, id) => indent,
+            _ => {
+                for child in document.children(id) {
+                    self.node(child, depth);
+                }
+                return;
+            }
+        };
+
+        let children: Vec<NodeId> = document.children(id)
+            .filter(|&child| match document.node(child).node_type {
+                NodeType::Text(ref text) => !trim(text).is_empty(),
+                _ => true,
+            })
+            .collect();
+        // Elements that only contain text stay on one line.
+        let inline = children.iter()
+            .all(|&child| matches!(document.node(child).node_type, NodeType::Text(_)));
+        if inline {
+            for child in children {
+                if let NodeType::Text(ref text) = document.node(child).node_type {
+                    escape(trim(text), false, &mut self.output);
+                }
+            }
+            return;
+        }
+
+        for (i, &child) in children.iter().enumerate() {
+            if i > 0 || !top_level {
+                self.output.push('\n');
+            }
+            self.push_indent(indent * depth);
+            match document.node(child).node_type {
+                NodeType::Text(ref text) => escape(trim(text), false, &mut self.output),
+                _ => self.node(child, depth),
+            }
+        }
+        if !top_level {
+            self.output.push('\n');
+            self.push_indent(indent * depth.saturating_sub(1));
+        }
+    }
+
+    fn push_indent(&mut self, width: usize) {
+        self.output.extend(::std::iter::repeat_n(' ', width));
+    }
+}
+
+/// Whether the text below `id` must be written out as it is, even when pretty-printing.
+fn preserves_whitespace(document: &Document, id: NodeId) -> bool {
+    ::std::iter::once(id)
+        .chain(document.ancestors(id))
+        .filter_map(|node| document.element(node))
+        .any(|elem| is_html(elem, NEWLINE_ELEMENTS) || is_html(elem, RAW_TEXT_ELEMENTS))
+}
+
+/// Whether `elem` is an HTML element named in `names`.
+fn is_html(elem: &ElementData, names: &[&str]) -> bool {
+    elem.namespace == Namespace::Html && names.contains(&&*elem.tag_name)
+}
+
+/// `text` without leading and trailing ASCII whitespace. Other whitespace, such as non-breaking
+/// spaces, is significant.
+fn trim(text: &str) -> &str {
+    text.trim_matches(|c| matches!(c, ' ' | '\t' | '\n' | '\x0c' | '\r'))
+}
+
+/// Append `text` to `output`, escaping the characters that are significant in text or, if
+/// `in_attribute` is set, in a double-quoted attribute value.
+fn escape(text: &str, in_attribute: bool, output: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '\u{a0}' => output.push_str("&nbsp;"),
+            '"' if in_attribute => output.push_str("&quot;"),
+            '<' if !in_attribute => output.push_str("&lt;"),
+            '>' if !in_attribute => output.push_str("&gt;"),
+            _ => output.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use html::Parser;
+    use super::{inner_html, SerializeOptions};
+
+    /// Parse `source` and serialize the children of its `body` element.
+    fn body(source: &str, indent: Option<usize>) -> String {
+        let document = Parser::parse(source.to_string());
+        let body = document.get_elements_by_tag_name(document.root(), "body")[0];
+        inner_html(&document, body, SerializeOptions { indent })
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(body("a &amp; b&nbsp;&lt;c&gt; \"d\" 'e'", None),
+                   "a &amp; b&nbsp;&lt;c&gt; \"d\" 'e'");
+        assert_eq!(body("<p title='&amp;&nbsp;\"<x>'>", None),
+                   "<p title=\"&amp;&nbsp;&quot;<x>\"></p>");
+    }
+
+    #[test]
+    fn raw_text_is_not_escaped() {
+        assert_eq!(body("<p><script>a && b < c</script><style>p > a {}</style>", None),
+                   "<p><script>a && b < c</script><style>p > a {}</style></p>");
+        assert_eq!(body("<xmp>&amp;<</xmp><textarea>&amp;<</textarea>", None),
+                   "<xmp>&amp;<</xmp><textarea>&amp;&lt;</textarea>");
+    }
+
+    #[test]
+    fn void_elements_have_no_end_tag() {
+        assert_eq!(body("<br><img src=x></img><input type=text><wbr>", None),
+                   "<br><img src=\"x\"><input type=\"text\"><wbr>");
+    }
+
+    #[test]
+    fn leading_newline_is_written_back() {
+        // The parser drops the first newline, so a second one must be written out twice.
+        for &source in &["<pre>\n\nx</pre>", "<textarea>\n\nx</textarea>",
+                         "<listing>\n\nx</listing>"] {
+            assert_eq!(body(source, None), source);
+        }
+        assert_eq!(body("<pre>\nx</pre>", None), "<pre>x</pre>");
+        let document = Parser::parse(body("<pre>\n\nx</pre>", None));
+        let pre = document.get_elements_by_tag_name(document.root(), "pre")[0];
+        assert_eq!(document.text_content(pre), "\nx");
+    }
+
+    #[test]
+    fn pretty_printing() {
+        assert_eq!(body("<div><p>a <b>b</b></p>\n  <p>  c  </p><br></div>x", Some(2)),
+                   "<div>\n  <p>\n    a\n    <b>b</b>\n  </p>\n  <p>c</p>\n  <br>\n</div>\nx");
+        assert_eq!(body("<ul><li>\u{a0}</li></ul>", Some(1)), "<ul>\n <li>&nbsp;</li>\n</ul>");
+    }
+
+    #[test]
+    fn pretty_printing_keeps_preserved_white_space() {
+        assert_eq!(body("<div><pre> a\n <b> b </b></pre><style> a { } </style></div>", Some(2)),
+                   "<div>\n  <pre> a\n <b> b </b></pre>\n  <style> a { } </style>\n</div>");
+        assert_eq!(body("<div><textarea>\n\n x </textarea></div>", Some(2)),
+                   "<div>\n  <textarea>\n\n x </textarea>\n</div>");
+    }
+}
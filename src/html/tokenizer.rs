@@ -0,0 +1,527 @@
+use std::collections::HashMap;
+
+use dom;
+use error::ParseError;
+use super::entities;
+use super::tree_builder::is_whitespace_char;
+
+/// A start or end tag.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tag {
+    /// The tag name, converted to ASCII lowercase.
+    pub name: String,
+    pub attributes: dom::AttrMap,
+    /// Whether the tag was written as `<name/>`.
+    pub self_closing: bool,
+}
+
+impl Tag {
+    /// A tag with no attributes.
+    pub fn new(name: &str) -> Tag {
+        Tag {
+            name: name.to_string(),
+            attributes: HashMap::new(),
+            self_closing: false,
+        }
+    }
+}
+
+/// A `<!DOCTYPE>` declaration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Doctype {
+    /// The doctype name, converted to ASCII lowercase.
+    pub name: Option<String>,
+    pub public_id: Option<String>,
+    pub system_id: Option<String>,
+    /// Set when the declaration is malformed, which always puts the document in quirks mode.
+    pub force_quirks: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Doctype(Doctype),
+    StartTag(Tag),
+    EndTag(Tag),
+    Text(String),
+    Comment(String),
+    /// The contents of a `<![CDATA[...]]>` section.
+    CData(String),
+    ProcessingInstruction { target: String, data: String },
+}
+
+/// How the content of an element is tokenized when it is not ordinary markup.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextContent {
+    /// Everything up to the element's end tag is text (`<style>`, `<script>`).
+    RawText,
+    /// Like `RawText`, but character references are decoded (`<title>`, `<textarea>`).
+    EscapableRawText,
+    /// The rest of the document is text (`<plaintext>`).
+    PlainText,
+}
+
+/// Splits HTML source into a sequence of [`Token`]s.
+///
+/// The source can be supplied all at once with [`Tokenizer::new`], or in pieces with
+/// [`Tokenizer::streaming`] and [`Tokenizer::feed`]. A streaming tokenizer returns text in pieces
+/// as it arrives, but any other token only once it has seen all of it, and discards input it has
+/// finished with.
+///
+/// Syntax errors are recovered from the way browsers do, and recorded as warnings.
+pub struct Tokenizer {
+    pos: usize,
+    input: String,
+    text_content: Option<(TextContent, String)>,
+    end_of_input: bool,
+    /// Whether the last input fed ended with a carriage return.
+    after_cr: bool,
+    discarded_bytes: usize,
+    discarded_lines: usize,
+    discarded_columns: usize,
+    warnings: Vec<ParseError>,
+}
+
+impl Tokenizer {
+    /// A tokenizer for a complete document.
+    pub fn new(input: String) -> Tokenizer {
+        let mut tokenizer = Tokenizer::streaming();
+        tokenizer.feed(&input);
+        tokenizer.end_of_input = true;
+        tokenizer
+    }
+
+    /// A tokenizer that receives its input incrementally.
+    pub fn streaming() -> Tokenizer {
+        Tokenizer {
+            pos: 0,
+            input: String::new(),
+            text_content: None,
+            end_of_input: false,
+            after_cr: false,
+            discarded_bytes: 0,
+            discarded_lines: 0,
+            discarded_columns: 0,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Append more source text.
+    ///
+    /// Line breaks are normalized as they arrive: CRLF pairs and lone CRs become LF.
+    pub fn feed(&mut self, text: &str) {
+        self.discard_consumed_input();
+        let text = match text.strip_prefix('\n') {
+            Some(rest) if self.after_cr => rest,
+            _ => text,
+        };
+        if text.is_empty() {
+            return;
+        }
+        self.after_cr = text.ends_with('\r');
+        if text.contains('\r') {
+            self.input.push_str(&text.replace("\r\n", "\n").replace('\r', "\n"));
+        } else {
+            self.input.push_str(text);
+        }
+    }
+
+    /// Signal that no more input will be fed, so that tokens cut off by the end of the input are
+    /// returned as they are.
+    pub fn end(&mut self) {
+        self.end_of_input = true;
+    }
+
+    /// Treat the content of the element named `name`, whose start tag was just returned, as text
+    /// rather than markup.
+    pub fn set_text_content(&mut self, kind: TextContent, name: &str) {
+        self.text_content = Some((kind, name.to_string()));
+    }
+
+    /// Take the warnings recorded for the tokens returned so far.
+    pub fn take_warnings(&mut self) -> Vec<ParseError> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Return the next token, or `None` if the input fed so far contains no complete token.
+    pub fn next_token(&mut self) -> Option<Token> {
+        let start = self.pos;
+        let text_content = self.text_content.clone();
+        let token = self.read_token();
+        let incomplete = match token {
+            Some(Token::Text(ref text)) => text.is_empty(),
+            _ => self.eof(),
+        };
+        if !self.end_of_input && incomplete {
+            // The token may continue in input that has not arrived yet.
+            self.pos = start;
+            self.text_content = text_content;
+            return None;
+        }
+        token
+    }
+
+    fn read_token(&mut self) -> Option<Token> {
+        if let Some((kind, name)) = self.text_content.take() {
+            let text = self.consume_text_content(kind, &name);
+            if !text.is_empty() {
+                return Some(Token::Text(text));
+            }
+            if self.text_content.is_some() {
+                // The end of the text has not arrived yet.
+                return None;
+            }
+        }
+        if self.eof() {
+            return None;
+        }
+        if self.starts_with("<!--") {
+            self.pos += 4;
+            return Some(self.parse_comment());
+        }
+        if self.starts_with_ignore_case("<!doctype") {
+            self.pos += 9;
+            return Some(self.parse_doctype());
+        }
+        if self.starts_with("<![CDATA[") {
+            self.pos += 9;
+            return Some(Token::CData(self.consume_until("]]>")));
+        }
+        if self.starts_with("<?") {
+            self.pos += 2;
+            return Some(self.parse_processing_instruction());
+        }
+        if self.starts_with("<!") {
+            self.pos += 2;
+            return Some(Token::Comment(self.consume_until(">")));
+        }
+        if self.starts_with("</>") {
+            self.pos += 3;
+            return self.read_token();
+        }
+        if self.starts_with("</") && !self.char_at(2).is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 2;
+            return Some(Token::Comment(self.consume_until(">")));
+        }
+        if self.starts_with("</") && self.char_at(2).is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 2;
+            return self.parse_tag().map(Token::EndTag);
+        }
+        if self.starts_with("<") && self.char_at(1).is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+            return self.parse_tag().map(Token::StartTag);
+        }
+        Some(self.parse_text())
+    }
+
+    fn next_char(&self) -> char {
+        self.input[self.pos..].chars().next().unwrap()
+    }
+
+    fn char_at(&self, offset: usize) -> Option<char> {
+        self.input[self.pos..].chars().nth(offset)
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.input[self.pos..].starts_with(s)
+    }
+
+    fn starts_with_ignore_case(&self, s: &str) -> bool {
+        self.input[self.pos..].get(..s.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(s))
+    }
+
+    fn eof(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    fn consume_char(&mut self) -> char {
+        let cur_char = self.next_char();
+        self.pos += cur_char.len_utf8();
+        cur_char
+    }
+
+    fn consume_while<F>(&mut self, test: F) -> String
+        where F: Fn(char) -> bool
+    {
+        let mut result = String::new();
+        while !self.eof() && test(self.next_char()) {
+            result.push(self.consume_char());
+        }
+        result
+    }
+
+    fn consume_whitespace(&mut self) {
+        self.consume_while(is_whitespace_char);
+    }
+
+    /// Consume input up to and including `terminator`, returning the text before it. Stops at the
+    /// end of the input if the terminator never appears.
+    fn consume_until(&mut self, terminator: &str) -> String {
+        let rest = &self.input[self.pos..];
+        let (text, consumed) = match rest.find(terminator) {
+            Some(end) => (&rest[..end], end + terminator.len()),
+            None => (rest, rest.len()),
+        };
+        let text = text.to_string();
+        self.pos += consumed;
+        text
+    }
+
+    fn error(&self, expected: &str) -> ParseError {
+        let mut error = ParseError::new(&self.input, self.pos, expected.to_string());
+        if error.line == 1 {
+            error.column += self.discarded_columns;
+        }
+        error.line += self.discarded_lines;
+        error.offset += self.discarded_bytes;
+        error
+    }
+
+    /// Drop the input before the current position, remembering enough about it to report
+    /// positions in later errors.
+    fn discard_consumed_input(&mut self) {
+        let consumed = &self.input[..self.pos];
+        match consumed.rfind('\n') {
+            Some(last_newline) => {
+                self.discarded_lines += consumed.matches('\n').count();
+                self.discarded_columns = consumed[last_newline + 1..].chars().count();
+            }
+            None => self.discarded_columns += consumed.chars().count(),
+        }
+        self.discarded_bytes += self.pos;
+        self.input.drain(..self.pos);
+        self.pos = 0;
+    }
+
+    fn parse_tag_name(&mut self) -> String {
+        self.consume_while(|c| !is_whitespace_char(c) && c != '/' && c != '>')
+            .to_ascii_lowercase()
+    }
+
+    /// Parse text up to the next `<`, or as much of it as has arrived.
+    fn parse_text(&mut self) -> Token {
+        let rest = &self.input[self.pos..];
+        // A `<` that does not start markup is text.
+        let skip = if rest.starts_with('<') { 1 } else { 0 };
+        let end = match rest[skip..].find('<') {
+            Some(end) => skip + end,
+            None => self.complete_text_length(rest, 1, true),
+        };
+        let text = entities::decode(&rest[..end], false);
+        self.pos += end;
+        Token::Text(text)
+    }
+
+    /// How much of `text`, which runs to the end of the input fed so far, can be returned before
+    /// more input arrives. A `<` in the last `markup_length` bytes might still start markup, and
+    /// if `decode` is set, a character reference at the end might still continue, so they are
+    /// held back.
+    fn complete_text_length(&self, text: &str, markup_length: usize, decode: bool) -> usize {
+        if self.end_of_input {
+            return text.len();
+        }
+        let mut end = match text.rfind('<') {
+            Some(start) if text.len() - start <= markup_length => start,
+            _ => text.len(),
+        };
+        if decode {
+            end = entities::trailing_reference(&text[..end]).unwrap_or(end);
+        }
+        end
+    }
+
+    /// Consume text up to the end tag for `name`, leaving the end tag itself in the input.
+    ///
+    /// If the end tag has not arrived yet, consumes as much as can be returned and stays in the
+    /// same state.
+    fn consume_text_content(&mut self, kind: TextContent, name: &str) -> String {
+        let rest = &self.input[self.pos..];
+        let end_tag = match kind {
+            TextContent::PlainText => None,
+            TextContent::RawText | TextContent::EscapableRawText => {
+                rest.match_indices("</")
+                    .map(|(i, _)| i)
+                    .find(|&i| {
+                        let after = &rest[i + 2..];
+                        after.get(..name.len()).is_some_and(|n| n.eq_ignore_ascii_case(name)) &&
+                        after[name.len()..]
+                            .chars()
+                            .next()
+                            .is_some_and(|c| is_whitespace_char(c) || c == '/' || c == '>')
+                    })
+            }
+        };
+        let end = match end_tag {
+            Some(end) => end,
+            None if kind == TextContent::PlainText => rest.len(),
+            None => {
+                let decode = kind == TextContent::EscapableRawText;
+                self.complete_text_length(rest, name.len() + 2, decode)
+            }
+        };
+        if end_tag.is_none() && !self.end_of_input {
+            self.text_content = Some((kind, name.to_string()));
+        }
+        let text = &rest[..end];
+        let text = match kind {
+            TextContent::EscapableRawText => entities::decode(text, false),
+            TextContent::RawText | TextContent::PlainText => text.to_string(),
+        };
+        self.pos += end;
+        text
+    }
+
+    fn parse_comment(&mut self) -> Token {
+        if self.starts_with(">") {
+            self.pos += 1;
+            return Token::Comment(String::new());
+        }
+        if self.starts_with("->") {
+            self.pos += 2;
+            return Token::Comment(String::new());
+        }
+        Token::Comment(self.consume_until("-->"))
+    }
+
+    fn parse_doctype(&mut self) -> Token {
+        let mut doctype = Doctype {
+            name: None,
+            public_id: None,
+            system_id: None,
+            force_quirks: false,
+        };
+        self.consume_whitespace();
+        let name = self.consume_while(|c| !is_whitespace_char(c) && c != '>');
+        if name.is_empty() {
+            doctype.force_quirks = true;
+        } else {
+            doctype.name = Some(name.to_ascii_lowercase());
+        }
+
+        self.consume_whitespace();
+        if self.starts_with_ignore_case("public") {
+            self.pos += 6;
+            self.consume_whitespace();
+            doctype.public_id = self.parse_doctype_identifier();
+            doctype.force_quirks |= doctype.public_id.is_none();
+            self.consume_whitespace();
+            doctype.system_id = self.parse_doctype_identifier();
+        } else if self.starts_with_ignore_case("system") {
+            self.pos += 6;
+            self.consume_whitespace();
+            doctype.system_id = self.parse_doctype_identifier();
+            doctype.force_quirks |= doctype.system_id.is_none();
+        }
+
+        self.consume_whitespace();
+        if !self.eof() && !self.starts_with(">") {
+            doctype.force_quirks = true;
+        }
+        self.consume_until(">");
+        Token::Doctype(doctype)
+    }
+
+    fn parse_doctype_identifier(&mut self) -> Option<String> {
+        if self.eof() || (self.next_char() != '"' && self.next_char() != '\'') {
+            return None;
+        }
+        let quote = self.consume_char();
+        let identifier = self.consume_while(|c| c != quote && c != '>');
+        if self.starts_with(&quote.to_string()) {
+            self.consume_char();
+        }
+        Some(identifier)
+    }
+
+    fn parse_processing_instruction(&mut self) -> Token {
+        let target = self.consume_while(|c| !is_whitespace_char(c) && c != '>' && c != '?');
+        self.consume_whitespace();
+        let mut data = self.consume_until(">");
+        if data.ends_with('?') {
+            data.pop();
+        }
+        Token::ProcessingInstruction { target, data }
+    }
+
+    /// Parse the rest of a tag. A tag cut off by the end of the input is dropped, as in browsers,
+    /// and `None` is returned.
+    fn parse_tag(&mut self) -> Option<Tag> {
+        let name = self.parse_tag_name();
+        let attributes = self.parse_attributes();
+        let self_closing = self.starts_with("/");
+        if self_closing {
+            self.consume_char();
+        }
+        if self.eof() {
+            if self.end_of_input {
+                let warning = self.error("the end of the tag");
+                self.warnings.push(warning);
+            }
+            return None;
+        }
+        self.consume_char();
+        Some(Tag {
+            name,
+            attributes,
+            self_closing,
+        })
+    }
+
+    /// Parse one attribute. An attribute without `=` is a boolean attribute with an empty value.
+    fn parse_attr(&mut self) -> (String, String) {
+        // An `=` can only start a name, since everywhere else it separates the name from the value.
+        let mut name = String::new();
+        if self.starts_with("=") {
+            name.push(self.consume_char());
+        }
+        name.push_str(&self.consume_while(|c| {
+            !is_whitespace_char(c) && !matches!(c, '/' | '>' | '=')
+        }));
+        let name = name.to_ascii_lowercase();
+
+        self.consume_whitespace();
+        if !self.starts_with("=") {
+            return (name, String::new());
+        }
+        self.consume_char();
+        self.consume_whitespace();
+        (name, self.parse_attr_value())
+    }
+
+    /// Parse an attribute value. A quoted value that is not closed runs to the end of the input.
+    fn parse_attr_value(&mut self) -> String {
+        if self.eof() {
+            return String::new();
+        }
+        let value = match self.next_char() {
+            open_quote @ '"' | open_quote @ '\'' => {
+                self.consume_char();
+                let value = self.consume_while(|c| c != open_quote);
+                if !self.eof() {
+                    self.consume_char();
+                }
+                value
+            }
+            _ => self.consume_while(|c| !is_whitespace_char(c) && c != '>'),
+        };
+        entities::decode(&value, true)
+    }
+
+    /// Parse the attributes of a tag. Names are case-insensitive, and when an attribute is
+    /// repeated the first occurrence wins.
+    fn parse_attributes(&mut self) -> dom::AttrMap {
+        let mut attributes = HashMap::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() || self.starts_with(">") || self.starts_with("/>") {
+                break;
+            }
+            if self.starts_with("/") {
+                self.consume_char();
+                continue;
+            }
+            let (name, value) = self.parse_attr();
+            attributes.entry(name).or_insert(value);
+        }
+        attributes
+    }
+}
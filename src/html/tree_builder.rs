@@ -0,0 +1,1491 @@
+use dom::{Document, Namespace, NodeId, NodeType, QuirksMode};
+use super::foreign;
+use super::tokenizer::{Doctype, Tag, TextContent, Token};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum InsertionMode {
+    Initial,
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    InHeadNoscript,
+    AfterHead,
+    InBody,
+    Text,
+    AfterBody,
+    AfterAfterBody,
+}
+
+enum FormattingEntry {
+    Marker,
+    Element(NodeId, Tag),
+}
+
+#[derive(Clone, Copy)]
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+}
+
+const SCOPE_BOUNDARIES: &[&str] = &["applet", "caption", "html", "table", "td", "th", "marquee",
+                                    "object", "template"];
+
+const IMPLIED_END_TAGS: &[&str] = &["dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt",
+                                    "rtc"];
+
+const SPECIAL: &[&str] = &["address", "applet", "area", "article", "aside", "base", "basefont",
+                           "bgsound", "blockquote", "body", "br", "button", "caption", "center",
+                           "col", "colgroup", "dd", "details", "dir", "div", "dl", "dt", "embed",
+                           "fieldset", "figcaption", "figure", "footer", "form", "frame",
+                           "frameset", "h1", "h2", "h3", "h4", "h5", "h6", "head", "header",
+                           "hgroup", "hr", "html", "iframe", "img", "input", "keygen", "li",
+                           "link", "listing", "main", "marquee", "menu", "meta", "nav",
+                           "noembed", "noframes", "noscript", "object", "ol", "p", "param",
+                           "plaintext", "pre", "script", "search", "section", "select", "source",
+                           "style", "summary", "table", "tbody", "td", "template", "textarea",
+                           "tfoot", "th", "thead", "title", "tr", "track", "ul", "wbr", "xmp"];
+
+const HEADINGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+const FORMATTING: &[&str] = &["a", "b", "big", "code", "em", "font", "i", "nobr", "s", "small",
+                              "strike", "strong", "tt", "u"];
+
+pub(super) const VOID_ELEMENTS: &[&str] = &["area", "base", "basefont", "bgsound", "br", "col",
+                                            "embed", "frame", "hr", "img", "input", "keygen",
+                                            "link", "meta", "param", "source", "track", "wbr"];
+
+/// Elements that close an open `p` element when they start.
+const CLOSES_P: &[&str] = &["address", "article", "aside", "blockquote", "center", "details",
+                            "dialog", "dir", "div", "dl", "fieldset", "figcaption", "figure",
+                            "footer", "form", "header", "hgroup", "hr", "listing", "main", "menu",
+                            "nav", "ol", "p", "pre", "search", "section", "summary", "table", "ul"];
+
+/// Elements whose end tag closes everything up to the matching start tag, if it is in scope.
+const BLOCK_END_TAGS: &[&str] = &["address", "article", "aside", "blockquote", "button", "center",
+                                  "details", "dialog", "dir", "div", "dl", "fieldset",
+                                  "figcaption", "figure", "footer", "form", "header", "hgroup",
+                                  "listing", "main", "menu", "nav", "ol", "pre", "search",
+                                  "section", "summary", "table", "ul"];
+
+/// Start tags that are handled by the "in head" rules wherever they appear.
+const HEAD_CONTENT: &[&str] = &["base", "basefont", "bgsound", "link", "meta", "noframes",
+                                "script", "style", "title"];
+
+/// Public identifiers that put a document in quirks mode when they start its doctype.
+const QUIRKS_PUBLIC_ID_PREFIXES: &[&str] = &[
+    "+//silmaril//dtd html pro v0r11 19970101//",
+    "-//as//dtd html 3.0 aswedit + extensions//",
+    "-//advasoft ltd//dtd html 3.0 aswedit + extensions//",
+    "-//ietf//dtd html 2.0 level 1//",
+    "-//ietf//dtd html 2.0 level 2//",
+    "-//ietf//dtd html 2.0 strict level 1//",
+    "-//ietf//dtd html 2.0 strict level 2//",
+    "-//ietf//dtd html 2.0 strict//",
+    "-//ietf//dtd html 2.0//",
+    "-//ietf//dtd html 2.1e//",
+    "-//ietf//dtd html 3.0//",
+    "-//ietf//dtd html 3.2 final//",
+    "-//ietf//dtd html 3.2//",
+    "-//ietf//dtd html 3//",
+    "-//ietf//dtd html level 0//",
+    "-//ietf//dtd html level 1//",
+    "-//ietf//dtd html level 2//",
+    "-//ietf//dtd html level 3//",
+    "-//ietf//dtd html strict level 0//",
+    "-//ietf//dtd html strict level 1//",
+    "-//ietf//dtd html strict level 2//",
+    "-//ietf//dtd html strict level 3//",
+    "-//ietf//dtd html strict//",
+    "-//ietf//dtd html//",
+    "-//metrius//dtd metrius presentational//",
+    "-//microsoft//dtd internet explorer 2.0 html strict//",
+    "-//microsoft//dtd internet explorer 2.0 html//",
+    "-//microsoft//dtd internet explorer 2.0 tables//",
+    "-//microsoft//dtd internet explorer 3.0 html strict//",
+    "-//microsoft//dtd internet explorer 3.0 html//",
+    "-//microsoft//dtd internet explorer 3.0 tables//",
+    "-//netscape comm. corp.//dtd html//",
+    "-//netscape comm. corp.//dtd strict html//",
+    "-//o'reilly and associates//dtd html 2.0//",
+    "-//o'reilly and associates//dtd html extended 1.0//",
+    "-//o'reilly and associates//dtd html extended relaxed 1.0//",
+    "-//sq//dtd html 2.0 hotmetal + extensions//",
+    "-//softquad software//dtd hotmetal pro 6.0::19990601::extensions to html 4.0//",
+    "-//softquad//dtd hotmetal pro 4.0::19971010::extensions to html 4.0//",
+    "-//spyglass//dtd html 2.0 extended//",
+    "-//sun microsystems corp.//dtd hotjava html//",
+    "-//sun microsystems corp.//dtd hotjava strict html//",
+    "-//w3c//dtd html 3 1995-03-24//",
+    "-//w3c//dtd html 3.2 draft//",
+    "-//w3c//dtd html 3.2 final//",
+    "-//w3c//dtd html 3.2//",
+    "-//w3c//dtd html 3.2s draft//",
+    "-//w3c//dtd html 4.0 frameset//",
+    "-//w3c//dtd html 4.0 transitional//",
+    "-//w3c//dtd html experimental 19960712//",
+    "-//w3c//dtd html experimental 970421//",
+    "-//w3c//dtd w3 html//",
+    "-//w3o//dtd w3 html 3.0//",
+    "-//webtechs//dtd mozilla html 2.0//",
+    "-//webtechs//dtd mozilla html//",
+];
+
+/// Whether `c` is ASCII whitespace, the only whitespace that separates things in HTML.
+pub(super) fn is_whitespace_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0c' | '\r' | ' ')
+}
+
+fn is_whitespace(text: &str) -> bool {
+    text.chars().all(is_whitespace_char)
+}
+
+fn is_comment(token: &Token) -> bool {
+    matches!(*token, Token::Comment(_) | Token::CData(_) | Token::ProcessingInstruction { .. })
+}
+
+/// Choose the document's compatibility mode from its doctype.
+fn quirks_mode_for(doctype: &Doctype) -> QuirksMode {
+    let public_id = doctype.public_id.as_ref().map(|id| id.to_ascii_lowercase());
+    let public_id = public_id.as_deref();
+    let system_id = doctype.system_id.as_ref().map(|id| id.to_ascii_lowercase());
+    let system_id = system_id.as_deref();
+    let public_starts_with = |prefix: &str| public_id.is_some_and(|id| id.starts_with(prefix));
+
+    if doctype.force_quirks || doctype.name.as_deref() != Some("html") ||
+       matches!(public_id, Some("-//w3o//dtd w3 html strict 3.0//en//") |
+                           Some("-/w3c/dtd html 4.0 transitional/en") |
+                           Some("html")) ||
+       system_id == Some("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd") ||
+       QUIRKS_PUBLIC_ID_PREFIXES.iter().any(|prefix| public_starts_with(prefix)) ||
+       (system_id.is_none() && (public_starts_with("-//w3c//dtd html 4.01 frameset//") ||
+                                public_starts_with("-//w3c//dtd html 4.01 transitional//"))) {
+        QuirksMode::Quirks
+    } else if public_starts_with("-//w3c//dtd xhtml 1.0 frameset//") ||
+              public_starts_with("-//w3c//dtd xhtml 1.0 transitional//") ||
+              (system_id.is_some() &&
+               (public_starts_with("-//w3c//dtd html 4.01 frameset//") ||
+                public_starts_with("-//w3c//dtd html 4.01 transitional//"))) {
+        QuirksMode::LimitedQuirks
+    } else {
+        QuirksMode::NoQuirks
+    }
+}
+
+/// Builds a DOM tree from a stream of tokens.
+///
+/// This implements the insertion modes of the HTML5 tree construction algorithm that apply to
+/// ordinary documents: implied `html`, `head` and `body` elements, implied end tags, void
+/// elements and the adoption agency algorithm for misnested formatting elements. Table and
+/// frameset insertion modes are not implemented; their elements are treated like any other
+/// element in the body, except that a new `tr` closes the open row and a new `td` or `th` closes
+/// the open cell.
+pub struct TreeBuilder {
+    document: Document,
+    open_elements: Vec<NodeId>,
+    active_formatting: Vec<FormattingEntry>,
+    mode: InsertionMode,
+    original_mode: InsertionMode,
+    head: Option<NodeId>,
+    text_content: Option<(TextContent, String)>,
+    ignore_next_newline: bool,
+}
+
+impl Default for TreeBuilder {
+    fn default() -> TreeBuilder {
+        TreeBuilder::new()
+    }
+}
+
+impl TreeBuilder {
+    pub fn new() -> TreeBuilder {
+        TreeBuilder {
+            document: Document::new(QuirksMode::NoQuirks),
+            open_elements: Vec::new(),
+            active_formatting: Vec::new(),
+            mode: InsertionMode::Initial,
+            original_mode: InsertionMode::Initial,
+            head: None,
+            text_content: None,
+            ignore_next_newline: false,
+        }
+    }
+
+    /// Feed the next token from the tokenizer into the tree.
+    ///
+    /// If the token opened an element whose content is not markup, such as `<style>` or
+    /// `<title>`, returns how the tokenizer should read that content and the element's name.
+    pub fn process_token(&mut self, token: Token) -> Option<(TextContent, String)> {
+        let token = match token {
+            Token::Text(ref text) if self.ignore_next_newline && text.starts_with('\n') => {
+                Token::Text(text[1..].to_string())
+            }
+            token => token,
+        };
+        self.ignore_next_newline = false;
+        // Outside the body, leading whitespace is handled differently from the text after it.
+        if let Token::Text(ref text) = token {
+            if self.mode != InsertionMode::InBody && self.mode != InsertionMode::Text {
+                let split = text.find(|c| !is_whitespace_char(c)).unwrap_or(0);
+                if split > 0 {
+                    self.dispatch(Token::Text(text[..split].to_string()));
+                    self.dispatch(Token::Text(text[split..].to_string()));
+                    return self.text_content.take();
+                }
+            }
+        }
+        self.dispatch(token);
+        self.text_content.take()
+    }
+
+    /// Process a token according to the current insertion mode, or as foreign content if the
+    /// current node is an SVG or MathML element.
+    fn dispatch(&mut self, token: Token) {
+        let foreign = self.open_elements.last().is_some_and(|&id| !self.is_html(id));
+        let token = match token {
+            Token::CData(data) if foreign => Token::Text(data),
+            token => token,
+        };
+        if foreign && !self.is_html_content(&token) {
+            self.foreign_content(token);
+        } else {
+            self.process_in_mode(self.mode, token);
+        }
+    }
+
+    /// Finish parsing and return the document.
+    pub fn finish(mut self) -> Document {
+        loop {
+            match self.mode {
+                InsertionMode::Initial => self.mode = InsertionMode::BeforeHtml,
+                InsertionMode::BeforeHtml => {
+                    self.insert_html(Tag::new("html"));
+                    self.mode = InsertionMode::BeforeHead;
+                }
+                InsertionMode::BeforeHead => {
+                    self.head = Some(self.insert_element(Tag::new("head")));
+                    self.mode = InsertionMode::InHead;
+                }
+                InsertionMode::InHead => {
+                    self.open_elements.pop();
+                    self.mode = InsertionMode::AfterHead;
+                }
+                InsertionMode::InHeadNoscript => {
+                    self.open_elements.pop();
+                    self.mode = InsertionMode::InHead;
+                }
+                InsertionMode::AfterHead => {
+                    self.insert_element(Tag::new("body"));
+                    self.mode = InsertionMode::InBody;
+                }
+                InsertionMode::Text => {
+                    self.open_elements.pop();
+                    self.mode = self.original_mode;
+                }
+                _ => break,
+            }
+        }
+        self.document
+    }
+
+    fn process_in_mode(&mut self, mode: InsertionMode, token: Token) {
+        match mode {
+            InsertionMode::Initial => self.initial(token),
+            InsertionMode::BeforeHtml => self.before_html(token),
+            InsertionMode::BeforeHead => self.before_head(token),
+            InsertionMode::InHead => self.in_head(token),
+            InsertionMode::InHeadNoscript => self.in_head_noscript(token),
+            InsertionMode::AfterHead => self.after_head(token),
+            InsertionMode::InBody => self.in_body(token),
+            InsertionMode::Text => self.text(token),
+            InsertionMode::AfterBody => self.after_body(token),
+            InsertionMode::AfterAfterBody => self.after_after_body(token),
+        }
+    }
+
+    /// Whether a token that arrives while the current node is an SVG or MathML element is still
+    /// handled as HTML, because the current node is an integration point that contains HTML.
+    fn is_html_content(&self, token: &Token) -> bool {
+        let current = *self.open_elements.last().unwrap();
+        let elem = self.document.element(current).unwrap();
+        let text_integration_point =
+            elem.namespace == Namespace::MathMl &&
+            foreign::MATHML_TEXT_INTEGRATION_POINTS.contains(&&*elem.tag_name);
+        match *token {
+            Token::StartTag(ref tag) if text_integration_point => {
+                tag.name != "mglyph" && tag.name != "malignmark"
+            }
+            Token::StartTag(ref tag) if tag.name == "svg" => {
+                elem.namespace == Namespace::MathMl && elem.tag_name == "annotation-xml" ||
+                self.is_html_integration_point(current)
+            }
+            Token::StartTag(_) | Token::Text(_) => {
+                text_integration_point || self.is_html_integration_point(current)
+            }
+            _ => false,
+        }
+    }
+
+    fn foreign_content(&mut self, token: Token) {
+        match token {
+            Token::Text(text) => self.insert_text(&text),
+            Token::Doctype(_) => {}
+            Token::StartTag(ref tag) if foreign::is_breakout(tag) => self.break_out(token.clone()),
+            Token::EndTag(ref tag) if tag.name == "br" || tag.name == "p" => {
+                self.break_out(token.clone())
+            }
+            Token::StartTag(tag) => {
+                let current = *self.open_elements.last().unwrap();
+                let namespace = self.document.element(current).unwrap().namespace;
+                self.insert_foreign_element(tag, namespace);
+            }
+            Token::EndTag(tag) => {
+                for index in (1..self.open_elements.len()).rev() {
+                    let node = self.open_elements[index];
+                    if index < self.open_elements.len() - 1 && self.is_html(node) {
+                        self.process_in_mode(self.mode, Token::EndTag(tag));
+                        return;
+                    }
+                    let elem = self.document.element(node).unwrap();
+                    if elem.tag_name.eq_ignore_ascii_case(&tag.name) {
+                        self.open_elements.truncate(index);
+                        return;
+                    }
+                }
+            }
+            token => self.insert_comment_at_current_node(token),
+        }
+    }
+
+    /// Close foreign elements until the current node is HTML or an integration point, then
+    /// handle `token` as HTML.
+    fn break_out(&mut self, token: Token) {
+        while let Some(&current) = self.open_elements.last() {
+            let elem = self.document.element(current).unwrap();
+            let text_integration_point =
+                elem.namespace == Namespace::MathMl &&
+                foreign::MATHML_TEXT_INTEGRATION_POINTS.contains(&&*elem.tag_name);
+            if self.is_html(current) || text_integration_point ||
+               self.is_html_integration_point(current) {
+                break;
+            }
+            self.open_elements.pop();
+        }
+        self.process_in_mode(self.mode, token);
+    }
+
+    fn reprocess(&mut self, mode: InsertionMode, token: Token) {
+        self.mode = mode;
+        self.process_in_mode(mode, token);
+    }
+
+    fn initial(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => {}
+            ref token if is_comment(token) => {
+                self.insert_comment(self.document.root(), token.clone())
+            }
+            Token::Doctype(doctype) => {
+                self.document.set_quirks_mode(quirks_mode_for(&doctype));
+                let id = self.document.create_doctype(doctype.name.unwrap_or_default(),
+                                                      doctype.public_id,
+                                                      doctype.system_id);
+                self.append_child(self.document.root(), id);
+                self.mode = InsertionMode::BeforeHtml;
+            }
+            token => {
+                self.document.set_quirks_mode(QuirksMode::Quirks);
+                self.reprocess(InsertionMode::BeforeHtml, token);
+            }
+        }
+    }
+
+    fn before_html(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => {}
+            Token::Doctype(_) => {}
+            ref token if is_comment(token) => {
+                self.insert_comment(self.document.root(), token.clone())
+            }
+            Token::StartTag(tag) if tag.name == "html" => {
+                self.insert_html(tag);
+                self.mode = InsertionMode::BeforeHead;
+            }
+            Token::EndTag(ref tag) if !matches!(&*tag.name, "head" | "body" | "html" | "br") => {}
+            token => {
+                self.insert_html(Tag::new("html"));
+                self.reprocess(InsertionMode::BeforeHead, token);
+            }
+        }
+    }
+
+    fn before_head(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => {}
+            Token::Doctype(_) => {}
+            ref token if is_comment(token) => self.insert_comment_at_current_node(token.clone()),
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token.clone()),
+            Token::StartTag(tag) if tag.name == "head" => {
+                self.head = Some(self.insert_element(tag));
+                self.mode = InsertionMode::InHead;
+            }
+            Token::EndTag(ref tag) if !matches!(&*tag.name, "head" | "body" | "html" | "br") => {}
+            token => {
+                self.head = Some(self.insert_element(Tag::new("head")));
+                self.reprocess(InsertionMode::InHead, token);
+            }
+        }
+    }
+
+    fn in_head(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => self.insert_text(text),
+            Token::Doctype(_) => {}
+            ref token if is_comment(token) => self.insert_comment_at_current_node(token.clone()),
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token.clone()),
+            Token::StartTag(tag) if VOID_ELEMENTS.contains(&&*tag.name) &&
+                                    HEAD_CONTENT.contains(&&*tag.name) => {
+                self.insert_element(tag);
+                self.open_elements.pop();
+            }
+            Token::StartTag(tag) if tag.name == "title" => {
+                self.insert_text_element(tag, TextContent::EscapableRawText);
+            }
+            Token::StartTag(tag) if HEAD_CONTENT.contains(&&*tag.name) => {
+                self.insert_text_element(tag, TextContent::RawText);
+            }
+            Token::StartTag(tag) if tag.name == "noscript" => {
+                self.insert_element(tag);
+                self.mode = InsertionMode::InHeadNoscript;
+            }
+            Token::StartTag(ref tag) if tag.name == "head" => {}
+            Token::EndTag(ref tag) if tag.name == "head" => {
+                self.open_elements.pop();
+                self.mode = InsertionMode::AfterHead;
+            }
+            Token::EndTag(ref tag) if !matches!(&*tag.name, "body" | "html" | "br") => {}
+            token => {
+                self.open_elements.pop();
+                self.reprocess(InsertionMode::AfterHead, token);
+            }
+        }
+    }
+
+    /// The "in head noscript" insertion mode. Scripting is never enabled, so the content of a
+    /// `noscript` element in the head is parsed as markup, but only the elements allowed in the
+    /// head may appear in it; anything else closes it.
+    fn in_head_noscript(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {}
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token.clone()),
+            Token::EndTag(ref tag) if tag.name == "noscript" => {
+                self.open_elements.pop();
+                self.mode = InsertionMode::InHead;
+            }
+            Token::Text(ref text) if is_whitespace(text) => self.in_head(token.clone()),
+            ref token if is_comment(token) => self.in_head(token.clone()),
+            Token::StartTag(ref tag) if matches!(&*tag.name, "basefont" | "bgsound" | "link" |
+                                                             "meta" | "noframes" | "style") => {
+                self.in_head(token.clone())
+            }
+            Token::StartTag(ref tag) if matches!(&*tag.name, "head" | "noscript") => {}
+            Token::EndTag(ref tag) if tag.name != "br" => {}
+            token => {
+                self.open_elements.pop();
+                self.reprocess(InsertionMode::InHead, token);
+            }
+        }
+    }
+
+    fn after_head(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => self.insert_text(text),
+            Token::Doctype(_) => {}
+            ref token if is_comment(token) => self.insert_comment_at_current_node(token.clone()),
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token.clone()),
+            Token::StartTag(tag) if tag.name == "body" => {
+                self.insert_element(tag);
+                self.mode = InsertionMode::InBody;
+            }
+            Token::StartTag(ref tag) if HEAD_CONTENT.contains(&&*tag.name) => {
+                let head = self.head.expect("head element is created before the after head mode");
+                self.open_elements.push(head);
+                self.in_head(token.clone());
+                self.open_elements.retain(|&id| id != head);
+            }
+            Token::StartTag(ref tag) if tag.name == "head" => {}
+            Token::EndTag(ref tag) if !matches!(&*tag.name, "body" | "html" | "br") => {}
+            token => {
+                self.insert_element(Tag::new("body"));
+                self.reprocess(InsertionMode::InBody, token);
+            }
+        }
+    }
+
+    fn text(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) => self.insert_text(text),
+            Token::EndTag(_) => {
+                self.open_elements.pop();
+                self.mode = self.original_mode;
+            }
+            token => {
+                self.open_elements.pop();
+                let mode = self.original_mode;
+                self.reprocess(mode, token);
+            }
+        }
+    }
+
+    fn after_body(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => self.in_body(token.clone()),
+            ref token if is_comment(token) => {
+                let html = self.open_elements[0];
+                self.insert_comment(html, token.clone());
+            }
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token.clone()),
+            Token::EndTag(ref tag) if tag.name == "html" => {
+                self.mode = InsertionMode::AfterAfterBody;
+            }
+            token => self.reprocess(InsertionMode::InBody, token),
+        }
+    }
+
+    fn after_after_body(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => self.in_body(token.clone()),
+            Token::Doctype(_) => {}
+            ref token if is_comment(token) => {
+                self.insert_comment(self.document.root(), token.clone())
+            }
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token.clone()),
+            token => self.reprocess(InsertionMode::InBody, token),
+        }
+    }
+
+    fn in_body(&mut self, token: Token) {
+        match token {
+            Token::Text(text) => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_text(&text);
+            }
+            Token::StartTag(tag) => self.in_body_start_tag(tag),
+            Token::EndTag(tag) => self.in_body_end_tag(tag),
+            Token::Doctype(_) => {}
+            token => self.insert_comment_at_current_node(token),
+        }
+    }
+
+    fn in_body_start_tag(&mut self, mut tag: Tag) {
+        let name = tag.name.clone();
+        match &*name {
+            "html" => {
+                let html = self.open_elements[0];
+                self.merge_attributes(html, tag);
+            }
+            "body" => {
+                if let Some(&body) = self.open_elements.get(1) {
+                    if self.tag_name(body) == Some("body") {
+                        self.merge_attributes(body, tag);
+                    }
+                }
+            }
+            // A stray `head` start tag is a parse error and is ignored.
+            "head" => {}
+            name if HEAD_CONTENT.contains(&name) => self.in_head(Token::StartTag(tag)),
+            name if HEADINGS.contains(&name) => {
+                self.close_p_element_in_button_scope();
+                if self.current_tag_name().is_some_and(|n| HEADINGS.contains(&n)) {
+                    self.open_elements.pop();
+                }
+                self.insert_element(tag);
+            }
+            "hr" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag);
+                self.open_elements.pop();
+            }
+            "table" => {
+                if self.document.quirks_mode() != QuirksMode::Quirks {
+                    self.close_p_element_in_button_scope();
+                }
+                self.insert_element(tag);
+            }
+            "pre" | "listing" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag);
+                self.ignore_next_newline = true;
+            }
+            "plaintext" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag);
+                self.text_content = Some((TextContent::PlainText, name.clone()));
+            }
+            "xmp" => {
+                self.close_p_element_in_button_scope();
+                self.reconstruct_active_formatting_elements();
+                self.insert_text_element(tag, TextContent::RawText);
+            }
+            "textarea" => {
+                self.insert_text_element(tag, TextContent::EscapableRawText);
+                self.ignore_next_newline = true;
+            }
+            "iframe" | "noembed" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_text_element(tag, TextContent::RawText);
+            }
+            name if CLOSES_P.contains(&name) => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag);
+            }
+            "li" => {
+                self.close_list_item(&["li"]);
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag);
+            }
+            "dd" | "dt" => {
+                self.close_list_item(&["dd", "dt"]);
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag);
+            }
+            "tr" => {
+                self.close_table_part(&["tr"]);
+                self.insert_element(tag);
+            }
+            "td" | "th" => {
+                self.close_table_part(&["td", "th"]);
+                self.insert_element(tag);
+            }
+            "button" => {
+                if self.has_element_in_scope("button", Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&["button"]);
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag);
+            }
+            "a" => {
+                let open_a = self.formatting_element_after_marker("a");
+                if let Some(a) = open_a {
+                    self.adoption_agency("a");
+                    self.active_formatting
+                        .retain(|e| !matches!(*e, FormattingEntry::Element(id, _) if id == a));
+                    self.open_elements.retain(|&id| id != a);
+                }
+                self.reconstruct_active_formatting_elements();
+                let id = self.insert_element(tag.clone());
+                self.push_active_formatting_element(id, tag);
+            }
+            "nobr" => {
+                self.reconstruct_active_formatting_elements();
+                if self.has_element_in_scope("nobr", Scope::Default) {
+                    self.adoption_agency("nobr");
+                    self.reconstruct_active_formatting_elements();
+                }
+                let id = self.insert_element(tag.clone());
+                self.push_active_formatting_element(id, tag);
+            }
+            name if FORMATTING.contains(&name) => {
+                self.reconstruct_active_formatting_elements();
+                let id = self.insert_element(tag.clone());
+                self.push_active_formatting_element(id, tag);
+            }
+            "applet" | "marquee" | "object" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag);
+                self.active_formatting.push(FormattingEntry::Marker);
+            }
+            "image" => {
+                tag.name = "img".to_string();
+                self.in_body_start_tag(tag);
+            }
+            name if VOID_ELEMENTS.contains(&name) => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag);
+                self.open_elements.pop();
+            }
+            "math" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_foreign_element(tag, Namespace::MathMl);
+            }
+            "svg" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_foreign_element(tag, Namespace::Svg);
+            }
+            "option" | "optgroup" => {
+                if self.current_tag_name() == Some("option") {
+                    self.open_elements.pop();
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag);
+            }
+            _ => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag);
+            }
+        }
+    }
+
+    fn in_body_end_tag(&mut self, tag: Tag) {
+        match &*tag.name {
+            "body" | "html" => {
+                if !self.has_element_in_scope("body", Scope::Default) {
+                    return;
+                }
+                self.mode = InsertionMode::AfterBody;
+                if tag.name == "html" {
+                    self.after_body(Token::EndTag(tag));
+                }
+            }
+            name if BLOCK_END_TAGS.contains(&name) => {
+                if !self.has_element_in_scope(name, Scope::Default) {
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.pop_until(&[name]);
+            }
+            "p" => {
+                if !self.has_element_in_scope("p", Scope::Button) {
+                    self.insert_element(Tag::new("p"));
+                }
+                self.close_p_element();
+            }
+            "li" => {
+                if !self.has_element_in_scope("li", Scope::ListItem) {
+                    return;
+                }
+                self.generate_implied_end_tags(Some("li"));
+                self.pop_until(&["li"]);
+            }
+            name @ "dd" | name @ "dt" => {
+                if !self.has_element_in_scope(name, Scope::Default) {
+                    return;
+                }
+                self.generate_implied_end_tags(Some(name));
+                self.pop_until(&[name]);
+            }
+            name if HEADINGS.contains(&name) => {
+                if !HEADINGS.iter().any(|h| self.has_element_in_scope(h, Scope::Default)) {
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.pop_until(HEADINGS);
+            }
+            name if FORMATTING.contains(&name) => {
+                if !self.adoption_agency(name) {
+                    self.any_other_end_tag(name);
+                }
+            }
+            name @ "applet" | name @ "marquee" | name @ "object" => {
+                if !self.has_element_in_scope(name, Scope::Default) {
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.pop_until(&[name]);
+                self.clear_active_formatting_elements_to_last_marker();
+            }
+            "br" => self.in_body_start_tag(Tag::new("br")),
+            name => self.any_other_end_tag(name),
+        }
+    }
+
+    fn any_other_end_tag(&mut self, name: &str) {
+        for index in (0..self.open_elements.len()).rev() {
+            let node = self.open_elements[index];
+            if self.tag_name(node) == Some(name) {
+                self.generate_implied_end_tags(Some(name));
+                self.open_elements.truncate(index);
+                return;
+            }
+            if self.is_special(node) {
+                return;
+            }
+        }
+    }
+
+    /// Run the adoption agency algorithm for an end tag named `subject`.
+    ///
+    /// Returns false if there is no matching formatting element, in which case the end tag must
+    /// be handled like any other end tag.
+    fn adoption_agency(&mut self, subject: &str) -> bool {
+        let current = *self.open_elements.last().unwrap();
+        if self.tag_name(current) == Some(subject) && self.formatting_index(current).is_none() {
+            self.open_elements.pop();
+            return true;
+        }
+
+        for _ in 0..8 {
+            let formatting_element = match self.formatting_element_after_marker(subject) {
+                Some(id) => id,
+                None => return false,
+            };
+            let formatting_stack_index = match self.open_elements
+                .iter()
+                .position(|&id| id == formatting_element) {
+                Some(index) => index,
+                None => {
+                    self.remove_from_active_formatting(formatting_element);
+                    return true;
+                }
+            };
+            if !self.has_element_in_scope_by_id(formatting_element, Scope::Default) {
+                return true;
+            }
+
+            let furthest_block_index = (formatting_stack_index + 1..self.open_elements.len())
+                .find(|&i| self.is_special(self.open_elements[i]));
+            let furthest_block_index = match furthest_block_index {
+                Some(index) => index,
+                None => {
+                    self.open_elements.truncate(formatting_stack_index);
+                    self.remove_from_active_formatting(formatting_element);
+                    return true;
+                }
+            };
+            let furthest_block = self.open_elements[furthest_block_index];
+            let common_ancestor = self.open_elements[formatting_stack_index - 1];
+            let mut bookmark = self.formatting_index(formatting_element).unwrap();
+
+            let mut node_index = furthest_block_index;
+            let mut last_node = furthest_block;
+            let mut inner_loop_counter = 0;
+            loop {
+                inner_loop_counter += 1;
+                node_index -= 1;
+                let node = self.open_elements[node_index];
+                if node == formatting_element {
+                    break;
+                }
+                if inner_loop_counter > 3 {
+                    if let Some(index) = self.formatting_index(node) {
+                        self.active_formatting.remove(index);
+                        if index < bookmark {
+                            bookmark -= 1;
+                        }
+                    }
+                }
+                let list_index = match self.formatting_index(node) {
+                    Some(index) => index,
+                    None => {
+                        self.open_elements.remove(node_index);
+                        continue;
+                    }
+                };
+                let tag = self.element_tag(node);
+                let new_node = self.create_element(tag.clone());
+                self.active_formatting[list_index] = FormattingEntry::Element(new_node, tag);
+                self.open_elements[node_index] = new_node;
+                if last_node == furthest_block {
+                    bookmark = list_index + 1;
+                }
+                self.append_child(new_node, last_node);
+                last_node = new_node;
+            }
+
+            self.append_child(common_ancestor, last_node);
+
+            let tag = self.element_tag(formatting_element);
+            let new_element = self.create_element(tag.clone());
+            let children: Vec<NodeId> = self.document.children(furthest_block).collect();
+            for child in children {
+                self.append_child(new_element, child);
+            }
+            self.append_child(furthest_block, new_element);
+
+            let old_index = self.formatting_index(formatting_element).unwrap();
+            self.active_formatting.remove(old_index);
+            if old_index < bookmark {
+                bookmark -= 1;
+            }
+            let bookmark = bookmark.min(self.active_formatting.len());
+            self.active_formatting.insert(bookmark, FormattingEntry::Element(new_element, tag));
+
+            self.open_elements.retain(|&id| id != formatting_element);
+            let furthest_block_index = self.open_elements
+                .iter()
+                .position(|&id| id == furthest_block)
+                .unwrap();
+            self.open_elements.insert(furthest_block_index + 1, new_element);
+        }
+        true
+    }
+
+    fn reconstruct_active_formatting_elements(&mut self) {
+        let last = match self.active_formatting.last() {
+            None | Some(&FormattingEntry::Marker) => return,
+            Some(&FormattingEntry::Element(id, _)) => id,
+        };
+        if self.open_elements.contains(&last) {
+            return;
+        }
+
+        let mut index = self.active_formatting.len() - 1;
+        while index > 0 {
+            let open = match self.active_formatting[index - 1] {
+                FormattingEntry::Marker => true,
+                FormattingEntry::Element(id, _) => self.open_elements.contains(&id),
+            };
+            if open {
+                break;
+            }
+            index -= 1;
+        }
+
+        for entry in &mut self.active_formatting[index..] {
+            let tag = match *entry {
+                FormattingEntry::Element(_, ref tag) => tag.clone(),
+                FormattingEntry::Marker => unreachable!(),
+            };
+            let parent = *self.open_elements.last().unwrap();
+            let id = self.document.create_element(tag.name.clone(), tag.attributes.clone());
+            self.document.append_child(parent, id);
+            self.open_elements.push(id);
+            *entry = FormattingEntry::Element(id, tag);
+        }
+    }
+
+    fn push_active_formatting_element(&mut self, id: NodeId, tag: Tag) {
+        let mut matching = Vec::new();
+        for (index, entry) in self.active_formatting.iter().enumerate().rev() {
+            match *entry {
+                FormattingEntry::Marker => break,
+                FormattingEntry::Element(_, ref other) if *other == tag => matching.push(index),
+                FormattingEntry::Element(..) => {}
+            }
+        }
+        if matching.len() >= 3 {
+            self.active_formatting.remove(*matching.last().unwrap());
+        }
+        self.active_formatting.push(FormattingEntry::Element(id, tag));
+    }
+
+    fn clear_active_formatting_elements_to_last_marker(&mut self) {
+        while let Some(entry) = self.active_formatting.pop() {
+            if let FormattingEntry::Marker = entry {
+                break;
+            }
+        }
+    }
+
+    fn formatting_element_after_marker(&self, name: &str) -> Option<NodeId> {
+        for entry in self.active_formatting.iter().rev() {
+            match *entry {
+                FormattingEntry::Marker => return None,
+                FormattingEntry::Element(id, ref tag) if tag.name == name => return Some(id),
+                FormattingEntry::Element(..) => {}
+            }
+        }
+        None
+    }
+
+    fn formatting_index(&self, node: NodeId) -> Option<usize> {
+        self.active_formatting.iter().position(|entry| {
+            matches!(*entry, FormattingEntry::Element(id, _) if id == node)
+        })
+    }
+
+    fn remove_from_active_formatting(&mut self, node: NodeId) {
+        if let Some(index) = self.formatting_index(node) {
+            self.active_formatting.remove(index);
+        }
+    }
+
+    fn close_p_element_in_button_scope(&mut self) {
+        if self.has_element_in_scope("p", Scope::Button) {
+            self.close_p_element();
+        }
+    }
+
+    fn close_p_element(&mut self) {
+        self.generate_implied_end_tags(Some("p"));
+        self.pop_until(&["p"]);
+    }
+
+    /// Close an open `li` (or `dd`/`dt`) element before a new one starts.
+    fn close_list_item(&mut self, names: &[&str]) {
+        for index in (0..self.open_elements.len()).rev() {
+            let node = self.open_elements[index];
+            if let Some(name) = self.tag_name(node).filter(|name| names.contains(name)) {
+                let name = name.to_string();
+                self.generate_implied_end_tags(Some(&name));
+                self.pop_until(&[&name]);
+                return;
+            }
+            if self.is_special(node) &&
+               !matches!(self.tag_name(node), Some("address") | Some("div") | Some("p")) {
+                return;
+            }
+        }
+    }
+
+    /// Close an open row (or cell) in table scope, together with anything open inside it, before
+    /// a new one starts.
+    fn close_table_part(&mut self, names: &[&str]) {
+        for index in (0..self.open_elements.len()).rev() {
+            match self.tag_name(self.open_elements[index]) {
+                Some(name) if names.contains(&name) => {
+                    self.generate_implied_end_tags(None);
+                    self.open_elements.truncate(index);
+                    return;
+                }
+                // A cell never closes one in an enclosing row.
+                Some("tr") | Some("table") | Some("html") | Some("template") => return,
+                _ => {}
+            }
+        }
+    }
+
+    fn generate_implied_end_tags(&mut self, except: Option<&str>) {
+        while let Some(name) = self.current_tag_name() {
+            if Some(name) == except || !IMPLIED_END_TAGS.contains(&name) {
+                break;
+            }
+            self.open_elements.pop();
+        }
+    }
+
+    /// Pop elements until one with one of the given names has been popped.
+    fn pop_until(&mut self, names: &[&str]) {
+        while let Some(id) = self.open_elements.pop() {
+            if self.tag_name(id).is_some_and(|name| names.contains(&name)) {
+                break;
+            }
+        }
+    }
+
+    fn has_element_in_scope(&self, name: &str, scope: Scope) -> bool {
+        for &id in self.open_elements.iter().rev() {
+            if self.tag_name(id) == Some(name) {
+                return true;
+            }
+            if self.is_scope_boundary(id, scope) {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn has_element_in_scope_by_id(&self, target: NodeId, scope: Scope) -> bool {
+        for &id in self.open_elements.iter().rev() {
+            if id == target {
+                return true;
+            }
+            if self.is_scope_boundary(id, scope) {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn is_scope_boundary(&self, id: NodeId, scope: Scope) -> bool {
+        let name = match self.tag_name(id) {
+            Some(name) => name,
+            None => return self.is_foreign_boundary(id),
+        };
+        SCOPE_BOUNDARIES.contains(&name) ||
+        match scope {
+            Scope::Default => false,
+            Scope::ListItem => name == "ol" || name == "ul",
+            Scope::Button => name == "button",
+        }
+    }
+
+    fn is_special(&self, id: NodeId) -> bool {
+        match self.tag_name(id) {
+            Some(name) => SPECIAL.contains(&name),
+            None => self.is_foreign_boundary(id),
+        }
+    }
+
+    /// Whether `id` is one of the SVG and MathML elements that bound scopes and count as
+    /// special, which are the elements that can contain HTML.
+    fn is_foreign_boundary(&self, id: NodeId) -> bool {
+        let elem = match self.document.element(id) {
+            Some(elem) => elem,
+            None => return false,
+        };
+        match elem.namespace {
+            Namespace::MathMl => {
+                elem.tag_name == "annotation-xml" ||
+                foreign::MATHML_TEXT_INTEGRATION_POINTS.contains(&&*elem.tag_name)
+            }
+            Namespace::Svg => foreign::SVG_HTML_INTEGRATION_POINTS.contains(&&*elem.tag_name),
+            _ => false,
+        }
+    }
+
+    fn is_html(&self, id: NodeId) -> bool {
+        self.document.element(id).is_some_and(|elem| elem.namespace == Namespace::Html)
+    }
+
+    /// Whether `id` is an SVG or MathML element whose children are HTML.
+    fn is_html_integration_point(&self, id: NodeId) -> bool {
+        let elem = match self.document.element(id) {
+            Some(elem) => elem,
+            None => return false,
+        };
+        match elem.namespace {
+            Namespace::MathMl if elem.tag_name == "annotation-xml" => {
+                elem.get_attribute("encoding").is_some_and(|encoding| {
+                    encoding.eq_ignore_ascii_case("text/html") ||
+                    encoding.eq_ignore_ascii_case("application/xhtml+xml")
+                })
+            }
+            Namespace::Svg => foreign::SVG_HTML_INTEGRATION_POINTS.contains(&&*elem.tag_name),
+            _ => false,
+        }
+    }
+
+    /// The tag name of `id` if it is an HTML element. The tree construction rules that refer to
+    /// elements by name only apply to HTML elements.
+    fn tag_name(&self, id: NodeId) -> Option<&str> {
+        self.document
+            .element(id)
+            .filter(|elem| elem.namespace == Namespace::Html)
+            .map(|elem| elem.tag_name.as_str())
+    }
+
+    fn current_tag_name(&self) -> Option<&str> {
+        self.open_elements.last().and_then(|&id| self.tag_name(id))
+    }
+
+    fn element_tag(&self, id: NodeId) -> Tag {
+        match self.document.element(id) {
+            Some(elem) => {
+                let mut tag = Tag::new(&elem.tag_name);
+                tag.attributes = elem.attributes().clone();
+                tag
+            }
+            None => panic!("node {:?} is not an element", id),
+        }
+    }
+
+    fn merge_attributes(&mut self, id: NodeId, tag: Tag) {
+        if let NodeType::Element(ref mut existing) = self.document.node_mut(id).node_type {
+            for (name, value) in tag.attributes {
+                if !existing.has_attribute(&name) {
+                    existing.set_attribute(&name, &value);
+                }
+            }
+        }
+    }
+
+    fn create_element(&mut self, tag: Tag) -> NodeId {
+        self.document.create_element(tag.name, tag.attributes)
+    }
+
+    fn insert_html(&mut self, tag: Tag) {
+        let id = self.create_element(tag);
+        self.append_child(self.document.root(), id);
+        self.open_elements.push(id);
+    }
+
+    /// Insert an element for `tag` at the current node and push it onto the stack.
+    fn insert_element(&mut self, tag: Tag) -> NodeId {
+        let parent = *self.open_elements.last().unwrap();
+        let id = self.create_element(tag);
+        self.append_child(parent, id);
+        self.open_elements.push(id);
+        id
+    }
+
+    /// Insert an SVG or MathML element for `tag` at the current node. It is pushed onto the stack
+    /// unless the tag is self-closing.
+    fn insert_foreign_element(&mut self, mut tag: Tag, namespace: Namespace) {
+        match namespace {
+            Namespace::Svg => {
+                foreign::adjust_svg_tag_name(&mut tag);
+                foreign::adjust_svg_attributes(&mut tag);
+            }
+            Namespace::MathMl => foreign::adjust_mathml_attributes(&mut tag),
+            _ => {}
+        }
+        let parent = *self.open_elements.last().unwrap();
+        let id = self.document.create_element_ns(namespace, tag.name, tag.attributes);
+        self.append_child(parent, id);
+        if !tag.self_closing {
+            self.open_elements.push(id);
+        }
+    }
+
+    /// Insert an element whose content is read as text, and switch to the text insertion mode
+    /// until its end tag.
+    fn insert_text_element(&mut self, tag: Tag, kind: TextContent) {
+        self.text_content = Some((kind, tag.name.clone()));
+        self.insert_element(tag);
+        self.original_mode = self.mode;
+        self.mode = InsertionMode::Text;
+    }
+
+    /// Insert a comment, processing instruction or CDATA section as a child of `parent`.
+    ///
+    /// CDATA sections are only meaningful in foreign content; in HTML content they become comments.
+    fn insert_comment(&mut self, parent: NodeId, token: Token) {
+        let id = match token {
+            Token::Comment(data) => self.document.create_comment(data),
+            Token::CData(data) => self.document.create_comment(format!("[CDATA[{}]]", data)),
+            Token::ProcessingInstruction { target, data } => {
+                self.document.create_processing_instruction(target, data)
+            }
+            _ => panic!("not a comment token"),
+        };
+        self.append_child(parent, id);
+    }
+
+    fn insert_comment_at_current_node(&mut self, token: Token) {
+        let parent = *self.open_elements.last().unwrap();
+        self.insert_comment(parent, token);
+    }
+
+    fn insert_text(&mut self, text: &str) {
+        let parent = match self.open_elements.last() {
+            Some(&id) => id,
+            None => return,
+        };
+        if let Some(last) = self.document.node(parent).last_child() {
+            if let NodeType::Text(ref mut existing) = self.document.node_mut(last).node_type {
+                existing.push_str(text);
+                return;
+            }
+        }
+        let id = self.document.create_text(text.to_string());
+        self.append_child(parent, id);
+    }
+
+    /// Append `child` to `parent`, detaching it from its previous parent first.
+    fn append_child(&mut self, parent: NodeId, child: NodeId) {
+        self.document.append_child(parent, child);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dom::{Namespace, QuirksMode};
+    use html::Parser;
+
+    /// Parse `source` and serialize the children of its `body` element.
+    fn body(source: &str) -> String {
+        let document = Parser::parse(source.to_string());
+        let body = document.get_elements_by_tag_name(document.root(), "body")[0];
+        document.inner_html(body)
+    }
+
+    #[test]
+    fn implies_html_head_and_body() {
+        let document = Parser::parse("x".to_string());
+        assert_eq!(document.outer_html(document.root()),
+                   "<html><head></head><body>x</body></html>");
+    }
+
+    #[test]
+    fn new_row_closes_open_row_and_cell() {
+        assert_eq!(body("<table><tr><td>a<tr><td>b"),
+                   "<table><tr><td>a</td></tr><tr><td>b</td></tr></table>");
+    }
+
+    #[test]
+    fn new_cell_closes_open_cell() {
+        assert_eq!(body("<table><tr><td>a<th>b<td>c</table>"),
+                   "<table><tr><td>a</td><th>b</th><td>c</td></tr></table>");
+    }
+
+    #[test]
+    fn row_in_nested_table_keeps_outer_row_open() {
+        assert_eq!(body("<table><tr><td><table><tr><td>a<tr><td>b"),
+                   "<table><tr><td><table><tr><td>a</td></tr><tr><td>b</td></tr></table>\
+                    </td></tr></table>");
+    }
+
+    #[test]
+    fn implied_end_tags() {
+        assert_eq!(body("<p>a<div>b</div>"), "<p>a</p><div>b</div>");
+        assert_eq!(body("<ul><li>a<li>b</ul>"), "<ul><li>a</li><li>b</li></ul>");
+        assert_eq!(body("<dl><dt>a<dd>b<dt>c</dl>"), "<dl><dt>a</dt><dd>b</dd><dt>c</dt></dl>");
+        assert_eq!(body("<h1>a<h2>b"), "<h1>a</h1><h2>b</h2>");
+    }
+
+    #[test]
+    fn end_tag_without_open_p_inserts_one() {
+        assert_eq!(body("<body>a</p>b"), "a<p></p>b");
+    }
+
+    #[test]
+    fn adoption_agency_reopens_formatting_elements() {
+        assert_eq!(body("<p><b>1<p>2</b>3"), "<p><b>1</b></p><p><b>2</b>3</p>");
+        assert_eq!(body("<b><i>x</b>y</i>"), "<b><i>x</i></b><i>y</i>");
+        assert_eq!(body("<a><p>x</a>y"), "<a></a><p><a>x</a>y</p>");
+    }
+
+    #[test]
+    fn nested_a_closes_outer_a() {
+        assert_eq!(body("<a>1<a>2"), "<a>1</a><a>2</a>");
+    }
+
+    #[test]
+    fn noscript_in_head() {
+        let html = |source: &str| {
+            let document = Parser::parse(source.to_string());
+            document.outer_html(document.root())
+        };
+        assert_eq!(html("<head><noscript><link rel=a></noscript></head><p>x"),
+                   "<html><head><noscript><link rel=\"a\"></noscript></head>\
+                    <body><p>x</p></body></html>");
+        assert_eq!(html("<head><noscript><img src=x></noscript></head><body><p>hi</p></body>"),
+                   "<html><head><noscript></noscript></head>\
+                    <body><img src=\"x\"><p>hi</p></body></html>");
+        assert_eq!(html("<head><noscript></head><p>x"),
+                   "<html><head><noscript></noscript></head><body><p>x</p></body></html>");
+        assert_eq!(html("<head><noscript>"),
+                   "<html><head><noscript></noscript></head><body></body></html>");
+        assert_eq!(html("<noscript> <style>a{}</style></noscript><title>t</title>"),
+                   "<html><head><noscript> <style>a{}</style></noscript><title>t</title>\
+                    </head><body></body></html>");
+    }
+
+    #[test]
+    fn only_ascii_whitespace_separates_tag_parts() {
+        assert_eq!(body("<a title=x\u{a0}y>z</a>"), "<a title=\"x&nbsp;y\">z</a>");
+        assert_eq!(body("<a\u{a0}title=x>z</a>"), "<a\u{a0}title=x>z</a\u{a0}title=x>");
+        assert_eq!(body("<a title\u{a0}=x>z</a>"), "<a title\u{a0}=\"x\">z</a>");
+        assert_eq!(body("<a\x0ctitle=x\x0cid=y>z</a>"), "<a id=\"y\" title=\"x\">z</a>");
+    }
+
+    #[test]
+    fn raw_text_end_tag_needs_ascii_whitespace() {
+        assert_eq!(body("<textarea>a</textarea\u{a0}>b</textarea>"),
+                   "<textarea>a&lt;/textarea&nbsp;&gt;b</textarea>");
+        assert_eq!(body("<textarea>a</textarea\x0c>b"), "<textarea>a</textarea>b");
+    }
+
+    #[test]
+    fn quirks_mode_from_doctype() {
+        let mode = |source: &str| Parser::parse(source.to_string()).quirks_mode();
+        let html_401 = "\"-//W3C//DTD HTML 4.01 Transitional//EN\"";
+        let xhtml_10 = "\"-//W3C//DTD XHTML 1.0 Transitional//EN\"";
+        let system_id = "\"http://www.w3.org/TR/html4/loose.dtd\"";
+        assert_eq!(mode("<!DOCTYPE html>"), QuirksMode::NoQuirks);
+        assert_eq!(mode("<!doctype HTML system 'about:legacy-compat'>"), QuirksMode::NoQuirks);
+        assert_eq!(mode("x"), QuirksMode::Quirks);
+        assert_eq!(mode("<!DOCTYPE>"), QuirksMode::Quirks);
+        assert_eq!(mode("<!DOCTYPE svg>"), QuirksMode::Quirks);
+        assert_eq!(mode("<!DOCTYPE html bogus>"), QuirksMode::Quirks);
+        assert_eq!(mode("<!DOCTYPE html PUBLIC>"), QuirksMode::Quirks);
+        assert_eq!(mode(&format!("<!DOCTYPE html PUBLIC {}>", html_401)), QuirksMode::Quirks);
+        assert_eq!(mode(&format!("<!DOCTYPE html PUBLIC {} {}>", html_401, system_id)),
+                   QuirksMode::LimitedQuirks);
+        assert_eq!(mode(&format!("<!DOCTYPE html PUBLIC {}>", xhtml_10)),
+                   QuirksMode::LimitedQuirks);
+        assert_eq!(mode("<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01//EN\">"),
+                   QuirksMode::NoQuirks);
+        assert_eq!(mode("<!DOCTYPE html PUBLIC \"-//IETF//DTD HTML 2.0//EN\">"),
+                   QuirksMode::Quirks);
+        assert_eq!(mode("<!DOCTYPE html PUBLIC 'html'>"), QuirksMode::Quirks);
+        assert_eq!(mode("<!DOCTYPE html SYSTEM \
+                         'http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd'>"),
+                   QuirksMode::Quirks);
+    }
+
+    #[test]
+    fn comments_and_other_markup_declarations() {
+        let html = |source: &str| {
+            let document = Parser::parse(source.to_string());
+            document.outer_html(document.root())
+        };
+        let empty = "<html><head></head><body></body></html>";
+        assert_eq!(html("<!-->"), format!("<!---->{}", empty));
+        assert_eq!(html("<!--->"), format!("<!---->{}", empty));
+        assert_eq!(html("<!--a-- b->-->"), format!("<!--a-- b->-->{}", empty));
+        assert_eq!(html("<!--a"), format!("<!--a-->{}", empty));
+        // Other markup declarations and `</` not followed by a letter are bogus comments.
+        assert_eq!(html("<!x>"), format!("<!--x-->{}", empty));
+        assert_eq!(html("</ x>"), format!("<!-- x-->{}", empty));
+        // CDATA sections are only text in foreign content.
+        assert_eq!(html("<![CDATA[a<b]]>"), format!("<!--[CDATA[a<b]]-->{}", empty));
+        assert_eq!(html("<?pi a b?>"), format!("<?pi a b>{}", empty));
+        assert_eq!(html("<?xml version='1.0'>"), format!("<?xml version='1.0'>{}", empty));
+        assert_eq!(html("<p>a<!--b-->c<?d e?>"),
+                   "<html><head></head><body><p>a<!--b-->c<?d e></p></body></html>");
+    }
+
+    /// The elements in the body of `source`, in document order, with their namespaces.
+    fn elements(source: &str) -> Vec<(Namespace, String)> {
+        let document = Parser::parse(source.to_string());
+        let body = document.get_elements_by_tag_name(document.root(), "body")[0];
+        document.descendant_elements(body)
+            .map(|(_, elem)| (elem.namespace, elem.tag_name.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn foreign_names_keep_their_case() {
+        assert_eq!(body("<svg viewbox='0 0 1 1'><foreignobject></foreignobject>\
+                         <lineargradient/></svg>"),
+                   "<svg viewBox=\"0 0 1 1\"><foreignObject></foreignObject>\
+                    <linearGradient></linearGradient></svg>");
+        assert_eq!(body("<math definitionurl=a><mi definitionurl=b></mi></math>"),
+                   "<math definitionURL=\"a\"><mi definitionURL=\"b\"></mi></math>");
+        // Only the names of the element's own namespace are adjusted.
+        assert_eq!(body("<svg definitionurl=a><foreignobject viewbox=b><p viewbox=c>"),
+                   "<svg definitionurl=\"a\"><foreignObject viewBox=\"b\">\
+                    <p viewbox=\"c\"></p></foreignObject></svg>");
+    }
+
+    #[test]
+    fn foreign_attribute_namespaces() {
+        let document = Parser::parse("<svg xlink:href=a xml:lang=en xmlns:xlink=b>".to_string());
+        let svg = document.get_elements_by_tag_name(document.root(), "svg")[0];
+        let svg = document.element(svg).unwrap();
+        assert_eq!(svg.namespace, Namespace::Svg);
+        assert_eq!(svg.get_attribute_ns(Some(Namespace::XLink), "href"), Some("a"));
+        assert_eq!(svg.get_attribute_ns(Some(Namespace::Xml), "lang"), Some("en"));
+        assert_eq!(svg.get_attribute_ns(Some(Namespace::Xmlns), "xlink"), Some("b"));
+        assert_eq!(svg.get_attribute_ns(None, "href"), None);
+    }
+
+    #[test]
+    fn html_start_tags_break_out_of_foreign_content() {
+        assert_eq!(elements("<svg><g><p>x</svg>"),
+                   [(Namespace::Svg, "svg".to_string()), (Namespace::Svg, "g".to_string()),
+                    (Namespace::Html, "p".to_string())]);
+        assert_eq!(body("<svg><g><p>x</svg>"), "<svg><g></g></svg><p>x</p>");
+        assert_eq!(body("<math><mi>a</mi><div>b"), "<math><mi>a</mi></math><div>b</div>");
+        // `font` only breaks out with one of the presentational attributes.
+        assert_eq!(body("<svg><font color=red>x</font></svg>"),
+                   "<svg></svg><font color=\"red\">x</font>");
+        assert_eq!(body("<svg><font>x</font></svg>"), "<svg><font>x</font></svg>");
+        assert_eq!(elements("<svg><font>x</font></svg>")[1], (Namespace::Svg, "font".to_string()));
+        // End tags for `br` and `p` break out too.
+        assert_eq!(body("<p><svg><g></p>x"), "<p><svg><g></g></svg></p>x");
+    }
+
+    #[test]
+    fn html_integration_points() {
+        let html = |name: &str| (Namespace::Html, name.to_string());
+        let svg = |name: &str| (Namespace::Svg, name.to_string());
+        let mathml = |name: &str| (Namespace::MathMl, name.to_string());
+        assert_eq!(elements("<svg><foreignobject><p>x</p><g></g></foreignobject></svg>"),
+                   [svg("svg"), svg("foreignObject"), html("p"), html("g")]);
+        assert_eq!(elements("<svg><title><b>x</b></title><desc><i>y</i></desc></svg>"),
+                   [svg("svg"), svg("title"), html("b"), svg("desc"), html("i")]);
+        assert_eq!(elements("<math><annotation-xml encoding=Text/HTML><p>x</p><svg>"),
+                   [mathml("math"), mathml("annotation-xml"), html("p"), svg("svg")]);
+        // Without an HTML encoding, annotation-xml is not an integration point, but it can
+        // still contain SVG.
+        assert_eq!(elements("<math><annotation-xml><svg></svg></annotation-xml><p>"),
+                   [mathml("math"), mathml("annotation-xml"), svg("svg"), html("p")]);
+        assert_eq!(body("<math><annotation-xml><p>x</p></annotation-xml></math>"),
+                   "<math><annotation-xml></annotation-xml></math><p>x</p>");
+        // MathML text integration points contain HTML, except for mglyph and malignmark.
+        assert_eq!(elements("<math><mtext><b>x</b><mglyph></mglyph></mtext><mi><svg>"),
+                   [mathml("math"), mathml("mtext"), html("b"), mathml("mglyph"), mathml("mi"),
+                    svg("svg")]);
+        // CDATA sections are text in foreign content.
+        assert_eq!(body("<svg><![CDATA[a<b]]></svg>"), "<svg>a&lt;b</svg>");
+    }
+
+    #[test]
+    fn stray_head_in_body_is_ignored() {
+        assert_eq!(body("<p>x<head><span>y</span>"), "<p>x<span>y</span></p>");
+    }
+}
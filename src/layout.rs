@@ -1,70 +1,119 @@
-struct Dimensions {
-    content: Rect,
+//! Basic CSS block layout, with inline content broken into lines.
+//!
+//! There are no fonts, so text is measured as if every character were half as wide as the font
+//! size, and every line 1.2 times as tall as the largest font on it.
+
+use css::Unit::Px;
+use css::Value::{Keyword, Length};
+use style::{Display, StyledNode, WhiteSpace};
+
+/// The width of a character of text, relative to its font size.
+const CHAR_WIDTH: f32 = 0.5;
+/// The height of a line of text, relative to its font size.
+const LINE_HEIGHT: f32 = 1.2;
+
+#[derive(Clone, Copy, Default, Debug)]
+/// The content area of a box plus its padding, border and margin edges.
+pub struct Dimensions {
+    pub content: Rect,
+
+    pub padding: EdgeSizes,
+    pub border: EdgeSizes,
+    pub margin: EdgeSizes,
+}
 
-    padding: EdgeSizes,
-    border: EdgeSizes,
-    margin: EdgeSizes,
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
 }
 
-struct Rect {
-    x: f32,
-    y: f32,
-    width: f32,
-    height: f32,
+#[derive(Clone, Copy, Default, Debug)]
+pub struct EdgeSizes {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
 }
 
-struct EdgeSizes {
-    left: f32,
-    right: f32,
-    top: f32,
-    bottom: f32,
+/// A node in the layout tree.
+pub struct LayoutBox<'a> {
+    pub dimensions: Dimensions,
+    pub box_type: BoxType<'a>,
+    pub children: Vec<LayoutBox<'a>>,
+    /// For an anonymous block, the lines its inline content was broken into.
+    pub lines: Vec<LineBox>,
 }
 
-struct LayoutBox<'a> {
-    dimensions: Dimensions,
-    box_types: BoxType<'a>,
-    children: Vec<LayoutBox<'a>>,
+/// One line of text in an anonymous block.
+#[derive(Clone, Debug, Default)]
+pub struct LineBox {
+    pub rect: Rect,
+    /// The text on the line, without collapsible spaces at either end.
+    pub text: String,
 }
 
-enum BoxType<'a> {
-    BlockNode(&'a StyleNode<'a>),
-    InlineNode(&'a StyleNode<'a>),
+pub enum BoxType<'a> {
+    BlockNode(&'a StyledNode),
+    InlineNode(&'a StyledNode),
     AnonymousBlock,
 }
 
-fn build_layou_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox {
+/// Build the layout tree for a style tree and lay it out inside `containing_block`.
+pub fn layout_tree<'a>(node: &'a StyledNode, mut containing_block: Dimensions) -> LayoutBox<'a> {
+    containing_block.content.height = 0.0;
+
+    let mut root_box = build_layout_tree(node);
+    root_box.layout(containing_block);
+    root_box
+}
+
+/// Build the tree of layout boxes without computing any dimensions.
+pub fn build_layout_tree<'a>(style_node: &'a StyledNode) -> LayoutBox<'a> {
     let mut root = LayoutBox::new(match style_node.display() {
-        Block => BlockNode(style_node),
-        Inline => InlineNode(style_node),
-        DisplayNone => panic!("Root node has display: none."),
+        Display::Block => BoxType::BlockNode(style_node),
+        Display::Inline => BoxType::InlineNode(style_node),
+        Display::None => panic!("Root node has display: none."),
     });
 
     for child in &style_node.children {
-        match childe.display() {
-            Block => root.children.push(build_layou_tree(child)),
-            Inline => root.get_inline_container().children.push(build_layot_tree(childl)),
-            DisplayNone => {}
+        match child.display() {
+            // Text that consists only of collapsed white space generates no box.
+            Display::Inline if child.text.as_deref() == Some("") => {}
+            Display::Block => root.children.push(build_layout_tree(child)),
+            Display::Inline => root.get_inline_container().children.push(build_layout_tree(child)),
+            Display::None => {}
         }
     }
-    return root;
+    root
 }
 
-impl LayoutBox {
+impl<'a> LayoutBox<'a> {
     fn new(box_type: BoxType) -> LayoutBox {
         LayoutBox {
-            box_type: box_type,
+            box_type,
             dimensions: Default::default(),
             children: Vec::new(),
+            lines: Vec::new(),
         }
     }
 
-    fn get_inline_container(&mut self) -> &mut LayoutBox {
+    fn get_style_node(&self) -> &'a StyledNode {
         match self.box_type {
-            InlineNode | AnonymousBlock => self,
-            BlockNode => {
+            BoxType::BlockNode(node) | BoxType::InlineNode(node) => node,
+            BoxType::AnonymousBlock => panic!("Anonymous block box has no style node"),
+        }
+    }
+
+    fn get_inline_container(&mut self) -> &mut LayoutBox<'a> {
+        match self.box_type {
+            BoxType::InlineNode(_) | BoxType::AnonymousBlock => self,
+            BoxType::BlockNode(_) => {
                 match self.children.last() {
-                    Some(&LayoutBox { box_type: AnonymousBlock, ..}) => {}
-                    _ => self.children.push(LayoutBox::new(AnonymousBlock)),
+                    Some(&LayoutBox { box_type: BoxType::AnonymousBlock, .. }) => {}
+                    _ => self.children.push(LayoutBox::new(BoxType::AnonymousBlock)),
                 }
                 self.children.last_mut().unwrap()
             }
@@ -73,9 +122,41 @@ impl LayoutBox {
 
     fn layout(&mut self, containing_block: Dimensions) {
         match self.box_type {
-            BlockNode => self.layout_block(containing_block),
-            InlineNode => {}
-            AnonymousBlock => {}
+            BoxType::BlockNode(_) => self.layout_block(containing_block),
+            BoxType::InlineNode(_) => {}
+            BoxType::AnonymousBlock => self.layout_inline_content(containing_block),
+        }
+    }
+
+    /// Lay out an anonymous block by breaking the text of its inline descendants into lines
+    /// that fit its width, as allowed by their `white-space` property.
+    fn layout_inline_content(&mut self, containing_block: Dimensions) {
+        let d = &mut self.dimensions;
+        d.content.x = containing_block.content.x;
+        d.content.y = containing_block.content.y + containing_block.content.height;
+        d.content.width = containing_block.content.width;
+
+        let mut breaker = LineBreaker::new(d.content.width);
+        for child in &self.children {
+            child.break_text(&mut breaker);
+        }
+        self.lines = breaker.finish();
+        for line in &mut self.lines {
+            line.rect.x = d.content.x;
+            line.rect.y = d.content.y + d.content.height;
+            d.content.height += line.rect.height;
+        }
+    }
+
+    fn break_text(&self, breaker: &mut LineBreaker) {
+        if let BoxType::InlineNode(node) = self.box_type {
+            if let Some(ref text) = node.text {
+                let font_size = node.lookup("font-size", &Length(16.0, Px)).to_px();
+                breaker.add_text(text, node.white_space(), font_size);
+            }
+            for child in &self.children {
+                child.break_text(breaker);
+            }
         }
     }
 
@@ -84,7 +165,7 @@ impl LayoutBox {
 
         self.calculate_block_position(containing_block);
 
-        self.layoutl_block_children();
+        self.layout_block_children();
 
         self.calculate_block_height();
     }
@@ -93,29 +174,29 @@ impl LayoutBox {
         let style = self.get_style_node();
 
         let auto = Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or(auto.clone());
+        let mut width = style.computed_value("width").unwrap_or(auto.clone());
 
         let zero = Length(0.0, Px);
 
-        let mut margin_left = style.lookup("margin-left", "margin", &zero);
-        let mut margin_right = style.lookup("margin-right", "margin", &zero);
+        let mut margin_left = style.lookup("margin-left", &zero);
+        let mut margin_right = style.lookup("margin-right", &zero);
 
-        let border_left = style.lookup("border-left-width", "border-widht", &zero);
-        let border_right = style.lookup("bordere-right-width", "border-width", &zero);
+        let border_left = style.lookup("border-left-width", &zero);
+        let border_right = style.lookup("border-right-width", &zero);
 
-        let padding_left = style.lookup("padding-left", "padding", &zero);
-        let padding_right = style.lookup("padding-right", "padding", &zero);
+        let padding_left = style.lookup("padding-left", &zero);
+        let padding_right = style.lookup("padding-right", &zero);
 
-        let total = [&margin_left,
-                     &margin_right,
-                     &border_left,
-                     &border_right,
-                     &padding_left,
-                     &padding_right,
-                     &width]
-                        .iter()
-                        .map(|v| v.to_px())
-                        .sum();
+        let total: f32 = [&margin_left,
+                          &margin_right,
+                          &border_left,
+                          &border_right,
+                          &padding_left,
+                          &padding_right,
+                          &width]
+                             .iter()
+                             .map(|v| v.to_px())
+                             .sum();
 
         if width != auto && total > containing_block.content.width {
             if margin_left == auto {
@@ -147,9 +228,9 @@ impl LayoutBox {
                     margin_right = Length(0.0, Px);
                 }
                 if underflow >= 0.0 {
-                    widht = Lengh(underflow, Px);
+                    width = Length(underflow, Px);
                 } else {
-                    widht = Length(0.0, Px);
+                    width = Length(0.0, Px);
                     margin_right = Length(margin_right.to_px() + underflow, Px);
                 }
             }
@@ -158,6 +239,18 @@ impl LayoutBox {
                 margin_right = Length(underflow / 2.0, Px);
             }
         }
+
+        let d = &mut self.dimensions;
+        d.content.width = width.to_px();
+
+        d.padding.left = padding_left.to_px();
+        d.padding.right = padding_right.to_px();
+
+        d.border.left = border_left.to_px();
+        d.border.right = border_right.to_px();
+
+        d.margin.left = margin_left.to_px();
+        d.margin.right = margin_right.to_px();
     }
 
     fn calculate_block_position(&mut self, containing_block: Dimensions) {
@@ -166,52 +259,147 @@ impl LayoutBox {
 
         let zero = Length(0.0, Px);
 
-        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-        d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+        d.margin.top = style.lookup("margin-top", &zero).to_px();
+        d.margin.bottom = style.lookup("margin-bottom", &zero).to_px();
 
-        d.border.top = style.lookup("border-top-width", "border-width", &zero).to_px();
-        d.border.bottom = style.lookup("border-bottom-width", "border-width", &zero).to_px();
+        d.border.top = style.lookup("border-top-width", &zero).to_px();
+        d.border.bottom = style.lookup("border-bottom-width", &zero).to_px();
 
-        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+        d.padding.top = style.lookup("padding-top", &zero).to_px();
+        d.padding.bottom = style.lookup("padding-bottom", &zero).to_px();
 
-        d.context.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
+        d.content.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
 
-        d.context.y = containing_block.content.height + containing_block.content.y +
-                      d.margin.left + d.border.top + d.padding.top;
+        d.content.y = containing_block.content.height + containing_block.content.y +
+                      d.margin.top + d.border.top + d.padding.top;
     }
 
     fn layout_block_children(&mut self) {
         let d = &mut self.dimensions;
         for child in &mut self.children {
             child.layout(*d);
-            d.content.height = d.content.height + child.dimensions.margin_box().height;
+            d.content.height += child.dimensions.margin_box().height;
         }
     }
 
     fn calculate_block_height(&mut self) {
-        if let Some(Length(h, Px)) = self.get_style_node().value("height") {
+        if let Some(Length(h, Px)) = self.get_style_node().computed_value("height") {
             self.dimensions.content.height = h;
         }
     }
 }
 
+/// Breaks a run of inline text into lines.
+struct LineBreaker {
+    available_width: f32,
+    lines: Vec<LineBox>,
+    line: LineBox,
+    /// The number and width of the collapsible spaces at the end of `line`, which are removed
+    /// if the line is broken after them.
+    trailing_spaces: (usize, f32),
+    /// Text after the last break opportunity, which has to go on one line.
+    word: LineBox,
+}
+
+impl LineBreaker {
+    fn new(available_width: f32) -> LineBreaker {
+        LineBreaker {
+            available_width,
+            lines: Vec::new(),
+            line: Default::default(),
+            trailing_spaces: (0, 0.0),
+            word: Default::default(),
+        }
+    }
+
+    /// Add text whose white space has already been collapsed. Where white space is preserved,
+    /// each newline is a forced line break; where lines wrap, each space or tab is a break
+    /// opportunity.
+    fn add_text(&mut self, text: &str, white_space: WhiteSpace, font_size: f32) {
+        let char_width = font_size * CHAR_WIDTH;
+        let line_height = font_size * LINE_HEIGHT;
+        for (i, segment) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.end_word();
+                self.line.rect.height = self.line.rect.height.max(line_height);
+                self.end_line();
+            }
+            for c in segment.chars() {
+                if !(white_space.wraps() && matches!(c, ' ' | '\t')) {
+                    self.word.text.push(c);
+                    self.word.rect.width += char_width;
+                    self.word.rect.height = self.word.rect.height.max(line_height);
+                    continue;
+                }
+                self.end_word();
+                if white_space.collapses_spaces() {
+                    if self.line.text.is_empty() {
+                        continue;
+                    }
+                    self.trailing_spaces.0 += 1;
+                    self.trailing_spaces.1 += char_width;
+                } else {
+                    // Preserved spaces hang at the end of a line instead of being removed.
+                    self.trailing_spaces = (0, 0.0);
+                }
+                self.line.text.push(c);
+                self.line.rect.width += char_width;
+                self.line.rect.height = self.line.rect.height.max(line_height);
+            }
+        }
+    }
+
+    /// Put the text since the last break opportunity on the current line, or on a new line if
+    /// it does not fit.
+    fn end_word(&mut self) {
+        if self.word.text.is_empty() {
+            return;
+        }
+        if !self.line.text.is_empty() &&
+           self.line.rect.width + self.word.rect.width > self.available_width {
+            self.end_line();
+        }
+        let word = ::std::mem::take(&mut self.word);
+        self.line.text.push_str(&word.text);
+        self.line.rect.width += word.rect.width;
+        self.line.rect.height = self.line.rect.height.max(word.rect.height);
+        self.trailing_spaces = (0, 0.0);
+    }
+
+    fn end_line(&mut self) {
+        let (count, width) = ::std::mem::replace(&mut self.trailing_spaces, (0, 0.0));
+        let mut line = ::std::mem::take(&mut self.line);
+        line.text.truncate(line.text.len() - count);
+        line.rect.width -= width;
+        self.lines.push(line);
+    }
+
+    /// The lines, with their sizes set but not their positions.
+    fn finish(mut self) -> Vec<LineBox> {
+        self.end_word();
+        if !self.line.text.is_empty() {
+            self.end_line();
+        }
+        self.lines
+    }
+}
+
 impl Dimensions {
-    fn padding_box(self) -> Rect {
+    pub fn padding_box(self) -> Rect {
         self.content.expanded_by(self.padding)
     }
 
-    fn border_box(self) -> Rect {
+    pub fn border_box(self) -> Rect {
         self.padding_box().expanded_by(self.border)
     }
 
-    fn margin_box(self) -> Rect {
+    pub fn margin_box(self) -> Rect {
         self.border_box().expanded_by(self.margin)
     }
 }
 
 impl Rect {
-    fn expanded_by(self, edge: EdgeSizes) -> Rect {
+    pub fn expanded_by(self, edge: EdgeSizes) -> Rect {
         Rect {
             x: self.x - edge.left,
             y: self.y - edge.top,
@@ -220,3 +408,86 @@ impl Rect {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use css;
+    use html::Parser;
+    use style::{self, StyleOptions};
+
+    /// Lay out `source` with `stylesheet` in a viewport 50px wide, and pass the root box to `f`.
+    fn with_layout<F: FnOnce(&LayoutBox)>(source: &str, stylesheet: &str, f: F) {
+        let document = Parser::parse(source.to_string());
+        let stylesheet = css::parse(stylesheet.to_string());
+        let options = StyleOptions { user_agent_stylesheet: None };
+        let style_root = style::style_tree(&document, &[&stylesheet], &options);
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 50.0;
+        f(&layout_tree(&style_root, viewport));
+    }
+
+    /// The text of each line of `source`, with ten characters of its 10px font fitting a line.
+    fn lines(source: &str, white_space: &str) -> Vec<String> {
+        let stylesheet = format!("html, body, p {{ display: block; }} \
+                                  p {{ font-size: 10px; white-space: {}; }}",
+                                 white_space);
+        let mut lines = Vec::new();
+        with_layout(source, &stylesheet, |root| collect_lines(root, &mut lines));
+        lines
+    }
+
+    fn collect_lines(layout_box: &LayoutBox, lines: &mut Vec<String>) {
+        lines.extend(layout_box.lines.iter().map(|line| line.text.clone()));
+        for child in &layout_box.children {
+            collect_lines(child, lines);
+        }
+    }
+
+    #[test]
+    fn normal_wraps_at_spaces() {
+        assert_eq!(lines("<p>  aaa bbb   ccc\nddd  </p>", "normal"), ["aaa bbb", "ccc ddd"]);
+        // A word that does not fit on a line of its own overflows it.
+        assert_eq!(lines("<p>aaa bbbbbbbbbbbb c</p>", "normal"), ["aaa", "bbbbbbbbbbbb", "c"]);
+    }
+
+    #[test]
+    fn no_break_between_inline_elements_without_space() {
+        assert_eq!(lines("<p>aaaaa <b>bbb</b>ccc</p>", "normal"), ["aaaaa", "bbbccc"]);
+        assert_eq!(lines("<p>aaaaa <b>bbb </b>ccc</p>", "normal"), ["aaaaa bbb", "ccc"]);
+    }
+
+    #[test]
+    fn nowrap_and_pre_do_not_wrap() {
+        assert_eq!(lines("<p>aaa bbb ccc\nddd</p>", "nowrap"), ["aaa bbb ccc ddd"]);
+        assert_eq!(lines("<p>aaa bbb  ccc\nddd</p>", "pre"), ["aaa bbb  ccc", "ddd"]);
+        assert_eq!(lines("<p>a\n\nb</p>", "pre"), ["a", "", "b"]);
+    }
+
+    #[test]
+    fn pre_wrap_wraps_and_keeps_spaces() {
+        assert_eq!(lines("<p>aaa  bbb ccc\nd</p>", "pre-wrap"), ["aaa  bbb ", "ccc", "d"]);
+    }
+
+    #[test]
+    fn pre_line_wraps_and_keeps_newlines() {
+        assert_eq!(lines("<p>aaa   bbb  \n  ccc ddd eee</p>", "pre-line"),
+                   ["aaa bbb", "ccc ddd", "eee"]);
+    }
+
+    #[test]
+    fn lines_give_the_block_its_height() {
+        let stylesheet = "html, body, p, div { display: block; } \
+                          p { font-size: 10px; } div { height: 5px; }";
+        with_layout("<p>aaa bbb ccc</p><div></div>", stylesheet, |root| {
+            let body = &root.children[0].children[1];
+            let (p, div) = (&body.children[0], &body.children[1]);
+            let lines = &p.children[0].lines;
+            assert_eq!(lines.len(), 2);
+            assert_eq!((lines[1].rect.y, lines[1].rect.width, lines[1].rect.height),
+                       (12.0, 15.0, 12.0));
+            assert_eq!(p.dimensions.content.height, 24.0);
+            assert_eq!(div.dimensions.content.y, 24.0);
+        });
+    }
+}
@@ -0,0 +1,131 @@
+//! A toy browser engine.
+//!
+//! Rendering is a pipeline of separate stages, each of which is exposed as its own module:
+//!
+//! * [`html`] parses markup into a [`dom::Document`].
+//! * [`css`] parses a [`css::StyleSheet`].
+//! * [`style`] matches the stylesheet against the DOM to build a [`style::StyledNode`] tree.
+//! * [`layout`] turns the style tree into a [`layout::LayoutBox`] tree with computed dimensions.
+//! * [`painting`] rasterizes the layout tree onto a [`painting::Canvas`].
+//!
+//! [`render`] runs the whole pipeline in one call. Malformed HTML and invalid CSS are recovered
+//! from the way browsers do, and a [`Viewport`] is checked when it is created, so rendering
+//! cannot fail.
+
+extern crate encoding_rs;
+
+pub mod css;
+pub mod dom;
+pub mod error;
+pub mod html;
+pub mod layout;
+pub mod painting;
+pub mod style;
+
+/// The largest viewport that can be rendered, in pixels, to keep the canvas's memory use bounded.
+pub const MAX_VIEWPORT_PIXELS: u32 = 1 << 26;
+
+/// Size of the area a document is rendered into, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    width: f32,
+    height: f32,
+}
+
+impl Viewport {
+    /// A viewport of at least one pixel in each direction and at most [`MAX_VIEWPORT_PIXELS`] in
+    /// total. Fractional sizes are allowed, but the canvas only covers whole pixels.
+    pub fn new(width: f32, height: f32) -> Result<Viewport, error::ViewportError> {
+        for &dimension in &[width, height] {
+            if !(dimension.is_finite() && dimension >= 1.0) {
+                return Err(error::ViewportError::InvalidDimension(dimension));
+            }
+        }
+        if f64::from(width.trunc()) * f64::from(height.trunc()) > f64::from(MAX_VIEWPORT_PIXELS) {
+            return Err(error::ViewportError::TooLarge { width, height });
+        }
+        Ok(Viewport { width, height })
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    /// The containing block that the root layout box is laid out in.
+    pub fn dimensions(&self) -> layout::Dimensions {
+        let mut dimensions: layout::Dimensions = Default::default();
+        dimensions.content.width = self.width;
+        dimensions.content.height = self.height;
+        dimensions
+    }
+}
+
+/// Parse `html` and `css`, then style, lay out and paint the document into a canvas of the
+/// viewport's size. Rules from `<style>` elements in the document apply after those in `css`.
+pub fn render(html: &str, css: &str, viewport: Viewport) -> painting::Canvas {
+    let document = html::Parser::parse(html.to_string());
+    let mut stylesheet = css::parse(css.to_string());
+    add_embedded_styles(&document, &mut stylesheet);
+    render_document(&document, &stylesheet, &Default::default(), viewport)
+}
+
+/// Parse the document's `<style>` elements and append their rules to `stylesheet`, returning
+/// warnings for anything that had to be skipped.
+pub fn add_embedded_styles(document: &dom::Document,
+                           stylesheet: &mut css::StyleSheet)
+                           -> Vec<error::ParseError> {
+    let mut warnings = Vec::new();
+    for source in html::embedded_styles(document) {
+        let (embedded, embedded_warnings) = css::parse_with_warnings(source);
+        stylesheet.rules.extend(embedded.rules);
+        warnings.extend(embedded_warnings);
+    }
+    warnings
+}
+
+/// Style, lay out and paint an already parsed document. `options` can replace or disable the
+/// built-in user-agent stylesheet.
+pub fn render_document(document: &dom::Document,
+                       stylesheet: &css::StyleSheet,
+                       options: &style::StyleOptions,
+                       viewport: Viewport)
+                       -> painting::Canvas {
+    let style_root = style::style_tree(document, &[stylesheet], options);
+    let layout_root = layout::layout_tree(&style_root, viewport.dimensions());
+    painting::paint(&layout_root, viewport.dimensions().content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::ViewportError;
+
+    #[test]
+    fn viewport_rejects_unusable_sizes() {
+        assert!(Viewport::new(800.0, 600.0).is_ok());
+        assert!(Viewport::new(1.0, 1.5).is_ok());
+        for &(width, height) in &[(0.0, 600.0), (800.0, -1.0), (0.5, 600.0), (800.0, f32::NAN),
+                                  (f32::INFINITY, 600.0)] {
+            match Viewport::new(width, height) {
+                Err(ViewportError::InvalidDimension(_)) => {}
+                other => panic!("{}x{}: {:?}", width, height, other),
+            }
+        }
+        assert_eq!(Viewport::new(1e20, 600.0),
+                   Err(ViewportError::TooLarge { width: 1e20, height: 600.0 }));
+        assert!(Viewport::new(8192.0, 8192.0).is_ok());
+        assert!(Viewport::new(8192.0, 8193.0).is_err());
+    }
+
+    #[test]
+    fn render_fills_the_viewport() {
+        let canvas = render("<p>hi</p>", "p { display: block; }",
+                            Viewport::new(3.5, 2.0).unwrap());
+        assert_eq!((canvas.width, canvas.height), (3, 2));
+        assert_eq!(canvas.pixels.len(), 6);
+    }
+}
@@ -1,3 +1,121 @@
+extern crate getopts;
+extern crate image;
+extern crate try_robinson;
+
+use std::fs::File;
+use std::io::Read;
+use std::process;
+
+use try_robinson::{css, html, style, Viewport};
+
 fn main() {
-    println!("Hello, world!");
+    let mut opts = getopts::Options::new();
+    opts.optopt("h", "html", "HTML document", "FILENAME");
+    opts.optopt("c", "css", "CSS stylesheet, applied before any <style> elements", "FILENAME");
+    opts.optopt("", "ua-css", "Replace the built-in default stylesheet", "FILENAME");
+    opts.optflag("", "no-ua-css", "Render without the built-in default stylesheet");
+    opts.optopt("w", "width", "Viewport width in pixels (default 800)", "PIXELS");
+    opts.optopt("", "height", "Viewport height in pixels (default 600)", "PIXELS");
+    opts.optopt("o", "output", "Output file (default output.png)", "FILENAME");
+    opts.optflag("", "help", "Print this help menu");
+
+    let args: Vec<String> = std::env::args().collect();
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => fail(&e.to_string()),
+    };
+    if matches.opt_present("help") {
+        print!("{}", opts.usage(&format!("Usage: {} [options]", args[0])));
+        return;
+    }
+
+    // Without any arguments, render the bundled example. A document passed with --html may carry
+    // its own <style> elements, so it needs no separate stylesheet.
+    let html_file = matches.opt_str("html").unwrap_or_else(|| "examples/test.html".to_string());
+    let css_file = match matches.opt_str("css") {
+        Some(file) => Some(file),
+        None if matches.opt_present("html") => None,
+        None => Some("examples/test.css".to_string()),
+    };
+    let width = parse_dimension(&matches, "width", 800.0);
+    let height = parse_dimension(&matches, "height", 600.0);
+    let viewport = match Viewport::new(width, height) {
+        Ok(viewport) => viewport,
+        Err(e) => fail(&format!("Invalid viewport: {}", e)),
+    };
+    let filename = matches.opt_str("output").unwrap_or_else(|| "output.png".to_string());
+
+    let file = File::open(&html_file).and_then(html::Parser::parse_reader_with_warnings);
+    let document = match file {
+        Ok((document, warnings)) => {
+            for warning in warnings {
+                eprintln!("{}:{}", html_file, warning);
+            }
+            document
+        }
+        Err(e) => fail(&format!("Error reading {}: {}", html_file, e)),
+    };
+    let mut stylesheet = match css_file {
+        Some(css_file) => {
+            let (stylesheet, warnings) = css::parse_with_warnings(read_source(&css_file));
+            for warning in warnings {
+                eprintln!("{}:{}", css_file, warning);
+            }
+            stylesheet
+        }
+        None => css::StyleSheet::empty(css::Origin::Author),
+    };
+    for warning in try_robinson::add_embedded_styles(&document, &mut stylesheet) {
+        eprintln!("{}: in <style>: {}", html_file, warning);
+    }
+    let user_agent_stylesheet = matches.opt_str("ua-css").map(|ua_css_file| {
+        let (stylesheet, warnings) = css::parse_with_warnings(read_source(&ua_css_file));
+        for warning in warnings {
+            eprintln!("{}:{}", ua_css_file, warning);
+        }
+        stylesheet
+    });
+    let options = style::StyleOptions {
+        user_agent_stylesheet: match user_agent_stylesheet {
+            _ if matches.opt_present("no-ua-css") => None,
+            Some(ref stylesheet) => Some(stylesheet),
+            None => Some(style::user_agent_stylesheet()),
+        },
+    };
+    let canvas = try_robinson::render_document(&document, &stylesheet, &options, viewport);
+
+    let (w, h) = (canvas.width as u32, canvas.height as u32);
+    let buffer: Vec<u8> = canvas.pixels
+        .iter()
+        .flat_map(|c| [c.r, c.g, c.b, c.a])
+        .collect();
+    let img = image::RgbaImage::from_raw(w, h, buffer).unwrap();
+    match img.save_with_format(&filename, image::ImageFormat::Png) {
+        Ok(_) => println!("Saved output as {}", filename),
+        Err(e) => fail(&format!("Error saving output as {}: {}", filename, e)),
+    }
+}
+
+fn read_source(filename: &str) -> String {
+    let mut source = String::new();
+    match File::open(filename).and_then(|mut f| f.read_to_string(&mut source)) {
+        Ok(_) => source,
+        Err(e) => fail(&format!("Error reading {}: {}", filename, e)),
+    }
+}
+
+/// Parse a viewport dimension. Whether it is a usable size is checked by `Viewport::new`.
+fn parse_dimension(matches: &getopts::Matches, name: &str, default: f32) -> f32 {
+    match matches.opt_str(name) {
+        Some(s) => match s.parse::<f32>() {
+            Ok(n) => n,
+            Err(_) => fail(&format!("Invalid --{}: {} (expected a number of pixels)", name, s)),
+        },
+        None => default,
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
 }
@@ -1,21 +1,32 @@
-type DisplayList = Vec<DisplayCommand>;
+//! Painting of a layout tree into a pixel buffer.
 
-fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
+use css::{Color, Value};
+use layout::{BoxType, LayoutBox, Rect};
+
+pub type DisplayList = Vec<DisplayCommand>;
+
+/// Paint a layout tree onto a new canvas covering `bounds`.
+///
+/// Panics if `bounds` covers more pixels than can be allocated. `render` never does, because a
+/// `Viewport` is limited to `MAX_VIEWPORT_PIXELS`.
+pub fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
     let display_list = build_display_list(layout_root);
     let mut canvas = Canvas::new(bounds.width as usize, bounds.height as usize);
     for item in display_list {
-        canvs.paint_item(&item);
+        canvas.paint_item(&item);
     }
-    return canvas;
+    canvas
 }
-enum DisplayCommand {
+
+pub enum DisplayCommand {
     SolidColor(Color, Rect),
 }
 
-fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
+/// Flatten a layout tree into a list of drawing commands in painting order.
+pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
     let mut list = Vec::new();
     render_layout_box(&mut list, layout_root);
-    return list;
+    list
 }
 
 fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
@@ -27,20 +38,20 @@ fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
 }
 
 fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
-    get_color(layout_box, "background").map(|color| {
-        list.push(DisplayCommand::SolidColor(color, layout_box.dimensions.border_box()))
-    });
+    if let Some(color) = get_color(layout_box, "background") {
+        list.push(DisplayCommand::SolidColor(color, layout_box.dimensions.border_box()));
+    }
 }
 
 fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
     match layout_box.box_type {
-        BlockNode(style) | InlineNode(style) => {
-            match style.value(name) {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
+            match style.computed_value(name) {
                 Some(Value::ColorValue(color)) => Some(color),
                 _ => None,
             }
         }
-        AnonymousBlock => None,
+        BoxType::AnonymousBlock => None,
     }
 }
 
@@ -77,16 +88,17 @@ fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
     list.push(DisplayCommand::SolidColor(color,
                                          Rect {
                                              x: border_box.x,
-                                             y: border_box.y + border_box.height - border.bottom,
+                                             y: border_box.y + border_box.height - d.border.bottom,
                                              width: border_box.width,
                                              height: d.border.bottom,
                                          }));
 }
 
-struct Canvas {
-    pixels: Vec<Color>,
-    width: usize,
-    height: usize,
+/// A row-major buffer of RGBA pixels.
+pub struct Canvas {
+    pub pixels: Vec<Color>,
+    pub width: usize,
+    pub height: usize,
 }
 
 impl Canvas {
@@ -97,22 +109,22 @@ impl Canvas {
             b: 255,
             a: 255,
         };
-        return Canvas {
-            pixels: repeat(white).take(width * height).collect(),
-            width: width,
-            hegiht: height,
-        };
+        Canvas {
+            pixels: vec![white; width.checked_mul(height).expect("canvas size overflows")],
+            width,
+            height,
+        }
     }
 
-    fn paint_items(&mut self, item: &DisplayCommand) {
-        match item {
-            &DisplayCommand::SolidColor(color, rect) => {
+    fn paint_item(&mut self, item: &DisplayCommand) {
+        match *item {
+            DisplayCommand::SolidColor(color, rect) => {
                 let x0 = rect.x.clamp(0.0, self.width as f32) as usize;
                 let y0 = rect.y.clamp(0.0, self.height as f32) as usize;
                 let x1 = (rect.x + rect.width).clamp(0.0, self.width as f32) as usize;
-                let y1 = (rect.y + rect.hegiht).clamp(0, 0, self.height as f32) as usize;
-                for y in (y0..y1) {
-                    for x in (x0..x1) {
+                let y1 = (rect.y + rect.height).clamp(0.0, self.height as f32) as usize;
+                for y in y0..y1 {
+                    for x in x0..x1 {
                         self.pixels[x + y * self.width] = color;
                     }
                 }
@@ -120,11 +132,3 @@ impl Canvas {
         }
     }
 }
-
-trait FloatClamp : Float {
-    fn clamp(self, lower: Self, upper: Self) -> Self {
-        self.max(lower).min(upper)
-    }
-}
-
-impl<T: Folat> FloatClamp for T {}
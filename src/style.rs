@@ -1,11 +1,88 @@
-fn matches(elem: &ElementData, selector: Selector) -> bool {
+//! Code for applying CSS styles to the DOM.
+
+use std::collections::HashMap;
+use std::mem;
+use std::sync::OnceLock;
+
+use css::{self, AttributeOperator, AttributeSelector, CaseSensitivity, Combinator, ContentItem,
+          Declaration, Origin, PseudoClass, PseudoElement, Rule, Selector, SimpleSelector,
+          Specificity, StyleSheet, Value};
+use dom::{Document, ElementData, Namespace, NodeId, NodeType};
+
+/// Map from CSS property names to values.
+pub type PropertyMap = HashMap<String, Value>;
+
+/// Whether the element `id` matches `selector`.
+///
+/// Complex selectors are matched from right to left: the rightmost simple selector is checked
+/// against the element itself, and the rest against its ancestors or preceding siblings.
+fn matches(document: &Document, id: NodeId, selector: &Selector) -> bool {
+    matches_relative(document, id, selector, None)
+}
+
+/// Like `matches`, but if `anchor` is given, the element matched by the leftmost simple selector
+/// must also be related to it by the given combinator, as `:has()` requires.
+fn matches_relative(document: &Document,
+                    id: NodeId,
+                    selector: &Selector,
+                    anchor: Option<(NodeId, Combinator)>)
+                    -> bool {
+    let elem = match document.element(id) {
+        Some(elem) => elem,
+        None => return false,
+    };
     match *selector {
-        Simple(ref simple_selector) => matches_simple_selector(elem, simple_selecto),
+        Selector::Simple(ref simple_selector) => {
+            matches_simple_selector(document, id, elem, simple_selector) &&
+            anchor.is_none_or(|(anchor, combinator)| is_related(document, anchor, combinator, id))
+        }
+        Selector::Complex(ref left, combinator, ref simple_selector) => {
+            if !matches_simple_selector(document, id, elem, simple_selector) {
+                return false;
+            }
+            let matches_left = |id| matches_relative(document, id, left, anchor);
+            match combinator {
+                Combinator::Descendant => element_ancestors(document, id).any(matches_left),
+                Combinator::Child => {
+                    element_ancestors(document, id).next().is_some_and(matches_left)
+                }
+                Combinator::NextSibling => {
+                    preceding_elements(document, id).next().is_some_and(matches_left)
+                }
+                Combinator::SubsequentSibling => preceding_elements(document, id).any(matches_left),
+            }
+        }
+    }
+}
+
+/// Whether `anchor` is related to the element `id` by `combinator`, as in `anchor > id`.
+fn is_related(document: &Document, anchor: NodeId, combinator: Combinator, id: NodeId) -> bool {
+    match combinator {
+        Combinator::Descendant => element_ancestors(document, id).any(|id| id == anchor),
+        Combinator::Child => element_ancestors(document, id).next() == Some(anchor),
+        Combinator::NextSibling => preceding_elements(document, id).next() == Some(anchor),
+        Combinator::SubsequentSibling => preceding_elements(document, id).any(|id| id == anchor),
     }
 }
 
-fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
-    if selector.id.iter().any(|name| elem.tag_name != *name) {
+fn element_ancestors(document: &Document, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+    document.ancestors(id).filter(move |&id| document.element(id).is_some())
+}
+
+fn preceding_elements(document: &Document, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+    document.preceding_siblings(id).filter(move |&id| document.element(id).is_some())
+}
+
+fn following_elements(document: &Document, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+    document.following_siblings(id).filter(move |&id| document.element(id).is_some())
+}
+
+fn matches_simple_selector(document: &Document,
+                           id: NodeId,
+                           elem: &ElementData,
+                           selector: &SimpleSelector)
+                           -> bool {
+    if selector.tag_name.iter().any(|name| elem.tag_name != *name) {
         return false;
     }
 
@@ -13,80 +90,1257 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
         return false;
     }
 
-    let elem_classes = elem.classes();
-    if selector.class.iter().any(|class| !elem_classes.contains(&**class)) {
+    if selector.class.iter().any(|class| !elem.has_class(class)) {
+        return false;
+    }
+
+    if selector.attributes.iter().any(|attribute| !matches_attribute_selector(elem, attribute)) {
         return false;
     }
 
-    return true;
+    selector.pseudo_classes
+        .iter()
+        .all(|pseudo_class| matches_pseudo_class(document, id, elem, pseudo_class))
+}
+
+fn matches_pseudo_class(document: &Document,
+                        id: NodeId,
+                        elem: &ElementData,
+                        pseudo_class: &PseudoClass)
+                        -> bool {
+    let same_type = |&sibling: &NodeId| {
+        document.element(sibling)
+            .is_some_and(|e| e.namespace == elem.namespace && e.tag_name == elem.tag_name)
+    };
+    let matches_any = |selectors: &[Selector], id| {
+        selectors.iter().any(|selector| matches(document, id, selector))
+    };
+    let matches_of = |of: &[Selector], id| of.is_empty() || matches_any(of, id);
+    match *pseudo_class {
+        PseudoClass::Root => {
+            let parent = document.node(id).parent().map(|parent| &document.node(parent).node_type);
+            matches!(parent, Some(NodeType::Document(_)))
+        }
+        PseudoClass::Empty => {
+            document.children(id).all(|child| match document.node(child).node_type {
+                NodeType::Element(_) => false,
+                NodeType::Text(ref text) => text.is_empty(),
+                _ => true,
+            })
+        }
+        PseudoClass::FirstChild => preceding_elements(document, id).next().is_none(),
+        PseudoClass::LastChild => following_elements(document, id).next().is_none(),
+        PseudoClass::OnlyChild => {
+            preceding_elements(document, id).next().is_none() &&
+            following_elements(document, id).next().is_none()
+        }
+        PseudoClass::FirstOfType => !preceding_elements(document, id).any(|id| same_type(&id)),
+        PseudoClass::LastOfType => !following_elements(document, id).any(|id| same_type(&id)),
+        PseudoClass::OnlyOfType => {
+            !preceding_elements(document, id).any(|id| same_type(&id)) &&
+            !following_elements(document, id).any(|id| same_type(&id))
+        }
+        PseudoClass::NthChild(nth, ref of) => {
+            matches_of(of, id) &&
+            nth.matches(position(preceding_elements(document, id), |&id| matches_of(of, id)))
+        }
+        PseudoClass::NthLastChild(nth, ref of) => {
+            matches_of(of, id) &&
+            nth.matches(position(following_elements(document, id), |&id| matches_of(of, id)))
+        }
+        PseudoClass::NthOfType(nth) => {
+            nth.matches(position(preceding_elements(document, id), same_type))
+        }
+        PseudoClass::NthLastOfType(nth) => {
+            nth.matches(position(following_elements(document, id), same_type))
+        }
+        PseudoClass::Not(ref selectors) => !matches_any(selectors, id),
+        PseudoClass::Is(ref selectors) | PseudoClass::Where(ref selectors) => {
+            matches_any(selectors, id)
+        }
+        PseudoClass::Has(ref selectors) => {
+            selectors.iter().any(|relative| {
+                let anchor = Some((id, relative.combinator));
+                let matches = |candidate| {
+                    matches_relative(document, candidate, &relative.selector, anchor)
+                };
+                match relative.combinator {
+                    Combinator::Descendant | Combinator::Child => {
+                        document.descendant_elements(id).any(|(candidate, _)| matches(candidate))
+                    }
+                    // The rightmost element may be a following sibling or one of its descendants.
+                    Combinator::NextSibling | Combinator::SubsequentSibling => {
+                        following_elements(document, id).any(|sibling| {
+                            matches(sibling) ||
+                            document.descendant_elements(sibling)
+                                .any(|(candidate, _)| matches(candidate))
+                        })
+                    }
+                }
+            })
+        }
+    }
+}
+
+/// The 1-based position of an element among the `siblings` on one side of it that `include`
+/// accepts.
+fn position<I, F>(siblings: I, include: F) -> i32
+    where I: Iterator<Item = NodeId>,
+          F: Fn(&NodeId) -> bool
+{
+    siblings.filter(include).count() as i32 + 1
+}
+
+/// HTML attributes whose values are compared case-insensitively by attribute selectors without
+/// an `s` flag.
+const CASE_INSENSITIVE_ATTRIBUTES: &[&str] = &[
+    "accept", "accept-charset", "align", "alink", "axis", "bgcolor", "charset", "checked",
+    "clear", "codetype", "color", "compact", "declare", "defer", "dir", "direction", "disabled",
+    "enctype", "face", "frame", "hreflang", "http-equiv", "lang", "language", "link", "media",
+    "method", "multiple", "nohref", "noresize", "noshade", "nowrap", "readonly", "rel", "rev",
+    "rules", "scope", "scrolling", "selected", "shape", "target", "text", "type", "valign",
+    "valuetype", "vlink",
+];
+
+fn matches_attribute_selector(elem: &ElementData, selector: &AttributeSelector) -> bool {
+    let actual = match elem.get_attribute(&selector.name) {
+        Some(value) => value,
+        None => return false,
+    };
+    let case_insensitive = match selector.case_sensitivity {
+        CaseSensitivity::Sensitive => false,
+        CaseSensitivity::Insensitive => true,
+        CaseSensitivity::Default => {
+            elem.namespace == Namespace::Html &&
+            CASE_INSENSITIVE_ATTRIBUTES.iter().any(|name| selector.name.eq_ignore_ascii_case(name))
+        }
+    };
+    let (actual, expected) = if case_insensitive {
+        (actual.to_ascii_lowercase(), selector.value.to_ascii_lowercase())
+    } else {
+        (actual.to_string(), selector.value.clone())
+    };
+    match selector.operator {
+        AttributeOperator::Exists => true,
+        AttributeOperator::Equals => actual == expected,
+        AttributeOperator::Includes => {
+            !expected.is_empty() && !expected.contains(is_whitespace) &&
+            actual.split(is_whitespace).any(|word| word == expected)
+        }
+        AttributeOperator::DashMatch => {
+            actual == expected ||
+            actual.starts_with(&expected) && actual[expected.len()..].starts_with('-')
+        }
+        AttributeOperator::Prefix => !expected.is_empty() && actual.starts_with(&expected),
+        AttributeOperator::Suffix => !expected.is_empty() && actual.ends_with(&expected),
+        AttributeOperator::Substring => !expected.is_empty() && actual.contains(&expected),
+    }
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0c')
 }
 
 type MatchedRule<'a> = (Specificity, &'a Rule);
 
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
+fn match_rule<'a>(document: &Document,
+                  id: NodeId,
+                  pseudo_element: Option<PseudoElement>,
+                  rule: &'a Rule)
+                  -> Option<MatchedRule<'a>> {
     rule.selectors
         .iter()
-        .find(|selector| matches(elem, *selector))
-        .map(|selector| (selector.Specificity(), rule))
+        .find(|selector| {
+            selector.pseudo_element() == pseudo_element && matches(document, id, selector)
+        })
+        .map(|selector| (selector.specificity(), rule))
 }
 
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a Stylesheet) -> Vec<MatchedRule<'a>> {
-    stylesheet.rules.iter().filter_map(|rule| match_rule(elem, rule)).collect()
+fn matching_rules<'a>(document: &Document,
+                      id: NodeId,
+                      pseudo_element: Option<PseudoElement>,
+                      stylesheet: &'a StyleSheet)
+                      -> Vec<MatchedRule<'a>> {
+    stylesheet.rules
+        .iter()
+        .filter_map(|rule| match_rule(document, id, pseudo_element, rule))
+        .collect()
 }
 
-fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap {
-    let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+/// The precedence of a declaration in the cascade: its origin and importance, whether it comes
+/// from a `style` attribute, and the specificity of its selector. Declarations with equal keys
+/// are ordered by their appearance.
+type CascadeKey = (u8, bool, Specificity);
+
+/// Rank origin and importance from lowest to highest precedence. Important declarations win
+/// over normal ones, and among them earlier origins win.
+fn cascade_layer(origin: Origin, important: bool) -> u8 {
+    match (important, origin) {
+        (false, Origin::UserAgent) => 0,
+        (false, Origin::User) => 1,
+        (false, Origin::Author) => 2,
+        (true, Origin::Author) => 3,
+        (true, Origin::User) => 4,
+        (true, Origin::UserAgent) => 5,
+    }
+}
 
-    rules.sort_by(|&(a,), &(b,)| a.cmp(&b));
-    for (_, rule) in rules {
-        for declaration in &rule.declarations {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+/// Cascade the values specified for the element `id`, or for one of its pseudo-elements, by the
+/// stylesheets, each at the given origin and in the order given, and by the element's `style`
+/// attribute.
+fn specified_values(document: &Document,
+                    id: NodeId,
+                    pseudo_element: Option<PseudoElement>,
+                    stylesheets: &[(Origin, &StyleSheet)])
+                    -> PropertyMap {
+    let style_attribute = document.element(id).and_then(|elem| elem.get_attribute("style"));
+    let inline_declarations = match (pseudo_element, style_attribute) {
+        (None, Some(style)) => css::parse_declarations(style.to_string()),
+        _ => Vec::new(),
+    };
+
+    let mut declarations: Vec<(CascadeKey, &Declaration)> = Vec::new();
+    for &(origin, stylesheet) in stylesheets {
+        for (specificity, rule) in matching_rules(document, id, pseudo_element, stylesheet) {
+            for declaration in &rule.declarations {
+                let layer = cascade_layer(origin, declaration.important);
+                declarations.push(((layer, false, specificity), declaration));
+            }
         }
     }
-    return values;
+    // A `style` attribute belongs to the author origin, and beats every selector.
+    for declaration in &inline_declarations {
+        let layer = cascade_layer(Origin::Author, declaration.important);
+        declarations.push(((layer, true, (0, 0, 0)), declaration));
+    }
+    // The sort is stable, so later declarations still win ties.
+    declarations.sort_by_key(|&(key, _)| key);
+
+    let mut values = HashMap::new();
+    for (_, declaration) in declarations {
+        values.insert(declaration.name.clone(), declaration.value.clone());
+    }
+    values
+}
+
+/// Options for building the style tree.
+pub struct StyleOptions<'a> {
+    /// The stylesheet applied at the user-agent origin, whatever its own origin is. `None`
+    /// leaves out default styles altogether.
+    pub user_agent_stylesheet: Option<&'a StyleSheet>,
+}
+
+impl<'a> Default for StyleOptions<'a> {
+    fn default() -> StyleOptions<'a> {
+        StyleOptions { user_agent_stylesheet: Some(user_agent_stylesheet()) }
+    }
 }
 
-pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a Stylesheet) -> StyledNode<'a> {
+/// The built-in stylesheet of default styles for HTML elements, such as `display: none` for
+/// `<head>` and margins for paragraphs and headings.
+pub fn user_agent_stylesheet() -> &'static StyleSheet {
+    static STYLESHEET: OnceLock<StyleSheet> = OnceLock::new();
+    STYLESHEET.get_or_init(|| {
+        let mut stylesheet = css::parse(include_str!("user_agent.css").to_string());
+        stylesheet.origin = Origin::UserAgent;
+        stylesheet
+    })
+}
+
+/// Apply stylesheets to an entire document, returning a `StyledNode` tree rooted at the
+/// document node. Stylesheets of the same origin take precedence in the order they are given,
+/// and all of them over the user-agent stylesheet in `options`.
+///
+/// Doctypes, comments and processing instructions are not part of the rendered content and are
+/// left out of the style tree. Elements whose `::before` or `::after` pseudo-elements have
+/// `content` get an extra first or last child for each.
+pub fn style_tree(document: &Document,
+                  stylesheets: &[&StyleSheet],
+                  options: &StyleOptions)
+                  -> StyledNode {
+    let mut cascade = Vec::new();
+    if let Some(stylesheet) = options.user_agent_stylesheet {
+        cascade.push((Origin::UserAgent, stylesheet));
+    }
+    cascade.extend(stylesheets.iter().map(|&stylesheet| (stylesheet.origin, stylesheet)));
+
+    let mut counters = Counters::default();
+    let mut root = style_node(document, document.root(), &cascade, None, &mut counters);
+    process_white_space(&mut root);
+    root
+}
+
+fn style_node(document: &Document,
+              id: NodeId,
+              stylesheets: &[(Origin, &StyleSheet)],
+              parent_values: Option<&PropertyMap>,
+              counters: &mut Counters)
+              -> StyledNode {
+    let mut text = None;
+    let specified_values = match document.node(id).node_type {
+        NodeType::Element(_) => specified_values(document, id, None, stylesheets),
+        NodeType::Document(_) => {
+            let mut values = HashMap::new();
+            values.insert("display".to_string(), Value::Keyword("block".to_string()));
+            values
+        }
+        NodeType::Text(ref data) => {
+            text = Some(data.clone());
+            HashMap::new()
+        }
+        _ => HashMap::new(),
+    };
+    let computed_values = compute_values(&specified_values, parent_values);
+    // Neither an element that generates no box nor its descendants affect counters or have
+    // pseudo-elements.
+    let generates_box = !counters.hidden && !is_display_none(&computed_values);
+    let hidden = mem::replace(&mut counters.hidden, !generates_box);
+    if generates_box {
+        counters.update(&computed_values);
+    }
+
+    // Counters created by the children stay in scope for their following siblings, but not
+    // beyond this node.
+    let scope = counters.enter_scope();
+    let mut children = Vec::new();
+    let generate = |pseudo_element, counters: &mut Counters| {
+        if !generates_box {
+            return None;
+        }
+        pseudo_element_node(document, id, pseudo_element, stylesheets, &computed_values, counters)
+    };
+    children.extend(generate(PseudoElement::Before, counters));
+    for child in document.children(id).filter(|&child| is_rendered(document, child)) {
+        children.push(style_node(document, child, stylesheets, Some(&computed_values), counters));
+    }
+    children.extend(generate(PseudoElement::After, counters));
+    counters.leave_scope(scope);
+    counters.hidden = hidden;
+
     StyledNode {
-        node: root,
-        specified_values: match root.node_type {
-            Element(ref elem) => specified_values(elem, stylesheet),
-            Text(_) => HashMap::new(),
-        },
-        children: root.children.iter().map(|child| style_tree(child, stylesheet)).collect(),
+        node: id,
+        pseudo_element: None,
+        specified_values,
+        computed_values,
+        text,
+        children,
+    }
+}
+
+/// The style node for a pseudo-element of the element `id`, holding its generated content as
+/// a text child, or `None` if it generates no box.
+fn pseudo_element_node(document: &Document,
+                       id: NodeId,
+                       pseudo_element: PseudoElement,
+                       stylesheets: &[(Origin, &StyleSheet)],
+                       parent_values: &PropertyMap,
+                       counters: &mut Counters)
+                       -> Option<StyledNode> {
+    let elem = document.element(id)?;
+    let specified_values = specified_values(document, id, Some(pseudo_element), stylesheets);
+    let computed_values = compute_values(&specified_values, Some(parent_values));
+    let items = match computed_values.get("content") {
+        Some(Value::Content(items)) => items.clone(),
+        _ => return None,
+    };
+    if is_display_none(&computed_values) {
+        return None;
     }
+    counters.update(&computed_values);
+
+    let text = StyledNode {
+        node: id,
+        pseudo_element: Some(pseudo_element),
+        specified_values: HashMap::new(),
+        computed_values: compute_values(&HashMap::new(), Some(&computed_values)),
+        text: Some(generated_content(elem, &items, counters)),
+        children: Vec::new(),
+    };
+    Some(StyledNode {
+        node: id,
+        pseudo_element: Some(pseudo_element),
+        specified_values,
+        computed_values,
+        text: None,
+        children: vec![text],
+    })
+}
+
+fn is_display_none(values: &PropertyMap) -> bool {
+    matches!(values.get("display"), Some(Value::Keyword(display)) if display == "none")
+}
+
+/// Every property the engine knows about, whether it is inherited, and its initial value.
+///
+/// Borders have no style, so their width starts at zero rather than `medium`.
+const PROPERTIES: &[(&str, bool, &str)] = &[
+    ("background", false, "transparent"),
+    ("border-color", false, "currentcolor"),
+    ("border-top-width", false, "0"),
+    ("border-right-width", false, "0"),
+    ("border-bottom-width", false, "0"),
+    ("border-left-width", false, "0"),
+    ("color", true, "#000000"),
+    ("content", false, "normal"),
+    ("counter-increment", false, "none"),
+    ("counter-reset", false, "none"),
+    ("display", false, "inline"),
+    ("font-family", true, "serif"),
+    ("font-size", true, "16px"),
+    ("font-style", true, "normal"),
+    ("font-weight", true, "normal"),
+    ("height", false, "auto"),
+    ("list-style-type", true, "disc"),
+    ("margin-top", false, "0"),
+    ("margin-right", false, "0"),
+    ("margin-bottom", false, "0"),
+    ("margin-left", false, "0"),
+    ("padding-top", false, "0"),
+    ("padding-right", false, "0"),
+    ("padding-bottom", false, "0"),
+    ("padding-left", false, "0"),
+    ("visibility", true, "visible"),
+    ("white-space", true, "normal"),
+    ("width", false, "auto"),
+];
+
+/// Whether a property is inherited, and its initial value.
+struct PropertyInfo {
+    inherited: bool,
+    initial: Value,
+}
+
+fn property_info(name: &str) -> Option<&'static PropertyInfo> {
+    static PROPERTY_INFO: OnceLock<HashMap<&str, PropertyInfo>> = OnceLock::new();
+    PROPERTY_INFO.get_or_init(|| {
+        PROPERTIES.iter()
+            .map(|&(name, inherited, initial)| {
+                let declarations = css::parse_declarations(format!("{}: {}", name, initial));
+                let initial = declarations.into_iter().next().unwrap().value;
+                (name, PropertyInfo { inherited, initial })
+            })
+            .collect()
+    }).get(name)
 }
 
-pub struct StyledNode<'a> {
-    pub node: &'a Node,
+fn is_inherited(name: &str) -> bool {
+    property_info(name).is_some_and(|info| info.inherited)
+}
+
+fn initial_value(name: &str) -> Option<Value> {
+    property_info(name).map(|info| info.initial.clone())
+}
+
+/// Resolve the specified values of a node against the computed values of its parent.
+///
+/// The result holds inherited properties and those that were specified, with `inherit`,
+/// `initial`, `unset` and `currentcolor` resolved. Other properties have their initial value.
+fn compute_values(specified: &PropertyMap, parent: Option<&PropertyMap>) -> PropertyMap {
+    let inherit = |name: &str| {
+        parent.and_then(|parent| parent.get(name).cloned()).or_else(|| initial_value(name))
+    };
+    let mut values: PropertyMap = match parent {
+        Some(parent) => {
+            parent.iter()
+                .filter(|&(name, _)| is_inherited(name))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect()
+        }
+        None => HashMap::new(),
+    };
+    for (name, value) in specified {
+        let keyword = match *value {
+            Value::Keyword(ref keyword) => keyword.as_str(),
+            _ => "",
+        };
+        let value = match keyword {
+            "inherit" => inherit(name),
+            "initial" => initial_value(name),
+            "unset" if is_inherited(name) => inherit(name),
+            "unset" => initial_value(name),
+            // The color property's own `currentcolor` is the color it would inherit.
+            "currentcolor" if name == "color" => inherit(name),
+            _ => Some(value.clone()),
+        };
+        match value {
+            Some(value) => values.insert(name.clone(), value),
+            None => values.remove(name),
+        };
+    }
+    let current_color = Value::Keyword("currentcolor".to_string());
+    if let Some(color) = values.get("color").cloned().or_else(|| initial_value("color")) {
+        for value in values.values_mut().filter(|value| **value == current_color) {
+            *value = color.clone();
+        }
+    }
+    values
+}
+
+fn generated_content(elem: &ElementData, items: &[ContentItem], counters: &Counters) -> String {
+    let mut content = String::new();
+    for item in items {
+        match *item {
+            ContentItem::String(ref string) => content.push_str(string),
+            ContentItem::Attr(ref name) => {
+                content.push_str(elem.get_attribute(name).unwrap_or(""))
+            }
+            ContentItem::Counter(ref name, ref style) => {
+                let value = counters.values(name).last().copied().unwrap_or(0);
+                content.push_str(&format_counter(value, style));
+            }
+            ContentItem::Counters(ref name, ref separator, ref style) => {
+                let values = counters.values(name);
+                let formatted: Vec<String> = if values.is_empty() {
+                    vec![format_counter(0, style)]
+                } else {
+                    values.iter().map(|&value| format_counter(value, style)).collect()
+                };
+                content.push_str(&formatted.join(separator));
+            }
+        }
+    }
+    content
+}
+
+/// The CSS counters in scope while the style tree is built, in document order.
+#[derive(Default)]
+struct Counters {
+    /// The nested instances of each counter, innermost last, each with its value and the depth
+    /// of the element that created it.
+    instances: HashMap<String, Vec<(i32, usize)>>,
+    /// The depth of the elements being styled: how many scopes have been entered.
+    depth: usize,
+    /// Whether the elements being styled are inside one with `display: none`.
+    hidden: bool,
+}
+
+impl Counters {
+    /// Apply the `counter-reset` and `counter-increment` properties of an element.
+    fn update(&mut self, values: &PropertyMap) {
+        if let Some(Value::Counters(resets)) = values.get("counter-reset") {
+            for (name, value) in resets {
+                let instances = self.instances.entry(name.clone()).or_default();
+                // An instance at the element's own depth was created by the element itself or
+                // by one of its preceding siblings, and is replaced rather than nested in, so
+                // that sibling lists are numbered independently.
+                match instances.last_mut() {
+                    Some(instance) if instance.1 == self.depth => instance.0 = *value,
+                    _ => instances.push((*value, self.depth)),
+                }
+            }
+        }
+        if let Some(Value::Counters(increments)) = values.get("counter-increment") {
+            for (name, value) in increments {
+                let instances = self.instances.entry(name.clone()).or_default();
+                // Incrementing a counter that is not in scope creates it.
+                if instances.is_empty() {
+                    instances.push((0, self.depth));
+                }
+                let instance = &mut instances.last_mut().unwrap().0;
+                // Like the values themselves, sums are clamped to the range of an `i32`.
+                *instance = instance.saturating_add(*value);
+            }
+        }
+    }
+
+    /// The values of every instance of a counter in scope, outermost first.
+    fn values(&self, name: &str) -> Vec<i32> {
+        self.instances
+            .get(name)
+            .map_or_else(Vec::new, |instances| instances.iter().map(|&(value, _)| value).collect())
+    }
+
+    /// Start styling the children of an element, recording how many instances of each counter
+    /// exist for `leave_scope`.
+    fn enter_scope(&mut self) -> HashMap<String, usize> {
+        self.depth += 1;
+        self.instances.iter().map(|(name, instances)| (name.clone(), instances.len())).collect()
+    }
+
+    /// Drop the instances created since the matching `enter_scope`, keeping the values of the
+    /// older ones.
+    fn leave_scope(&mut self, scope: HashMap<String, usize>) {
+        self.depth -= 1;
+        self.instances.retain(|name, instances| match scope.get(name) {
+            Some(&len) => {
+                instances.truncate(len);
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+
+/// Format a counter value in a `list-style-type`. Values a style cannot represent fall back to
+/// decimal.
+fn format_counter(value: i32, style: &str) -> String {
+    match style {
+        "none" => String::new(),
+        "disc" => "\u{2022}".to_string(),
+        "circle" => "\u{25e6}".to_string(),
+        "square" => "\u{25aa}".to_string(),
+        "lower-alpha" | "lower-latin" if value > 0 => alphabetic(value, b'a'),
+        "upper-alpha" | "upper-latin" if value > 0 => alphabetic(value, b'A'),
+        "lower-roman" if value > 0 && value < 4000 => roman(value).to_ascii_lowercase(),
+        "upper-roman" if value > 0 && value < 4000 => roman(value),
+        _ => value.to_string(),
+    }
+}
+
+/// `1` is `a`, `26` is `z`, `27` is `aa` and so on.
+fn alphabetic(mut value: i32, first: u8) -> String {
+    let mut letters = Vec::new();
+    while value > 0 {
+        value -= 1;
+        letters.push((first + (value % 26) as u8) as char);
+        value /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+fn roman(mut value: i32) -> String {
+    const NUMERALS: &[(i32, &str)] = &[(1000, "M"), (900, "CM"), (500, "D"), (400, "CD"),
+                                       (100, "C"), (90, "XC"), (50, "L"), (40, "XL"),
+                                       (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I")];
+    let mut result = String::new();
+    for &(number, numeral) in NUMERALS {
+        while value >= number {
+            result.push_str(numeral);
+            value -= number;
+        }
+    }
+    result
+}
+
+fn is_rendered(document: &Document, id: NodeId) -> bool {
+    matches!(document.node(id).node_type,
+             NodeType::Element(_) | NodeType::Text(_) | NodeType::Document(_))
+}
+
+/// A DOM node paired with the CSS property values that apply to it.
+///
+/// The boxes generated by `::before` and `::after` have style nodes too. Those have the
+/// `pseudo_element` set and `node` is their originating element.
+pub struct StyledNode {
+    pub node: NodeId,
+    pub pseudo_element: Option<PseudoElement>,
     pub specified_values: PropertyMap,
-    pub children: Vec<<StyleNode<'a>>,
+    /// The values of the properties that were specified or inherited, after resolving
+    /// keywords such as `inherit`. See [`StyledNode::computed_value`].
+    pub computed_values: PropertyMap,
+    /// For a text node, its text after white space has been collapsed according to the
+    /// `white-space` property. Empty if all of it collapsed away.
+    pub text: Option<String>,
+    pub children: Vec<StyledNode>,
+}
+
+/// Collapse white space in the text below a block, following the rules of CSS Text.
+///
+/// The text of all inline descendants of a block forms one run, so a space at the end of one
+/// text node swallows spaces at the start of the next. Nested blocks start runs of their own.
+fn process_white_space(block: &mut StyledNode) {
+    let mut run = Vec::new();
+    collect_inline_text(block, &mut run);
+    collapse_run(run);
+}
+
+fn collect_inline_text<'a>(node: &'a mut StyledNode, run: &mut Vec<(&'a mut String, WhiteSpace)>) {
+    for child in &mut node.children {
+        match child.display() {
+            Display::None => {}
+            Display::Block => {
+                collapse_run(mem::take(run));
+                process_white_space(child);
+            }
+            Display::Inline => {
+                let white_space = child.white_space();
+                match child.text {
+                    Some(ref mut text) => run.push((text, white_space)),
+                    None => collect_inline_text(child, run),
+                }
+            }
+        }
+    }
+}
+
+fn collapse_run(mut run: Vec<(&mut String, WhiteSpace)>) {
+    // Collapsible spaces at the start of a line are removed.
+    let mut after_space = true;
+    for i in 0..run.len() {
+        let (before, rest) = run.split_at_mut(i);
+        let (ref mut text, white_space) = rest[0];
+        **text = collapse_text(text, white_space, &mut after_space);
+        // So are those before a preserved line break, even in an earlier text node.
+        if white_space.collapses_spaces() && text.starts_with('\n') {
+            remove_trailing_space(before);
+        }
+    }
+    // And those at the end.
+    remove_trailing_space(&mut run);
+}
+
+/// Remove a collapsible space from the end of the last non-empty text in `run`.
+fn remove_trailing_space(run: &mut [(&mut String, WhiteSpace)]) {
+    if let Some(&mut (ref mut text, white_space)) = run.iter_mut().rev().find(|t| !t.0.is_empty()) {
+        if white_space.collapses_spaces() && text.ends_with(' ') {
+            text.pop();
+        }
+    }
+}
+
+/// Collapse white space in `text`. `after_space` says whether the text so far ends in a
+/// collapsible space, and is updated to say whether `text` does.
+fn collapse_text(text: &str, white_space: WhiteSpace, after_space: &mut bool) -> String {
+    if !white_space.collapses_spaces() {
+        if !text.is_empty() {
+            *after_space = false;
+        }
+        return text.to_string();
+    }
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' if white_space.preserves_newlines() => {
+                while result.ends_with(' ') {
+                    result.pop();
+                }
+                result.push('\n');
+                *after_space = true;
+            }
+            ' ' | '\t' | '\n' | '\x0c' => {
+                if !*after_space {
+                    result.push(' ');
+                }
+                *after_space = true;
+            }
+            _ => {
+                result.push(c);
+                *after_space = false;
+            }
+        }
+    }
+    result
 }
 
+#[derive(PartialEq)]
 pub enum Display {
     Inline,
     Block,
     None,
 }
 
+/// The value of the `white-space` property, which decides how white space in text is collapsed
+/// here and where layout may break lines.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WhiteSpace {
+    Normal,
+    Pre,
+    Nowrap,
+    PreWrap,
+    PreLine,
+}
+
+impl WhiteSpace {
+    /// Whether runs of spaces and tabs collapse into a single space.
+    pub fn collapses_spaces(self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::Nowrap | WhiteSpace::PreLine)
+    }
+
+    /// Whether line breaks in the text are kept as forced line breaks.
+    pub fn preserves_newlines(self) -> bool {
+        matches!(self, WhiteSpace::Pre | WhiteSpace::PreWrap | WhiteSpace::PreLine)
+    }
+
+    /// Whether lines may wrap at spaces and tabs when they get too long.
+    pub fn wraps(self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::PreWrap | WhiteSpace::PreLine)
+    }
+}
+
 impl StyledNode {
-    fn value(&self, name: &str) -> Option<Value> {
-        self.specified_values.get(name).map(|v| v.clone())
+    /// The specified value of a property, if any.
+    pub fn value(&self, name: &str) -> Option<Value> {
+        self.specified_values.get(name).cloned()
+    }
+
+    /// The computed value of a property: the cascaded value if it was specified, the parent's
+    /// value if the property is inherited, and otherwise its initial value. `None` for an
+    /// unknown property that was not specified.
+    pub fn computed_value(&self, name: &str) -> Option<Value> {
+        match self.computed_values.get(name) {
+            Some(value) => Some(value.clone()),
+            None if initial_value(name) == Some(Value::Keyword("currentcolor".to_string())) => {
+                self.computed_value("color")
+            }
+            None => initial_value(name),
+        }
+    }
+
+    /// The computed value of `name` if it was specified or inherited, or else `default`.
+    pub fn lookup(&self, name: &str, default: &Value) -> Value {
+        self.computed_values
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| default.clone())
     }
 
-    pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
-        self.value(name).unwrap_or_else(|| self.value(fallback_name)
-                        .unwrap_or_else(|| default.clone()))
+    /// The value of the `white-space` property.
+    pub fn white_space(&self) -> WhiteSpace {
+        match self.computed_value("white-space") {
+            Some(Value::Keyword(s)) => match &*s {
+                "pre" => WhiteSpace::Pre,
+                "nowrap" => WhiteSpace::Nowrap,
+                "pre-wrap" => WhiteSpace::PreWrap,
+                "pre-line" => WhiteSpace::PreLine,
+                _ => WhiteSpace::Normal,
+            },
+            _ => WhiteSpace::Normal,
+        }
     }
 
-    fn display(&self) -> Display {
-        match.value("display") {
-            Some(Keyword(s)) => match &*s {
-                "block" => Display::Block,
+    /// The value of the `display` property. List items, tables and their parts, flex and grid
+    /// containers are laid out as blocks.
+    pub fn display(&self) -> Display {
+        match self.computed_value("display") {
+            Some(Value::Keyword(s)) => match &*s {
+                "block" | "list-item" | "flow-root" | "flex" | "grid" | "table" => Display::Block,
+                s if s.starts_with("table-") => Display::Block,
                 "none" => Display::None,
-                => Display::Inline
+                _ => Display::Inline,
+            },
+            _ => Display::Inline,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use html::Parser;
+
+    /// Style `source` with `stylesheet` and the built-in user-agent stylesheet.
+    fn style(source: &str, stylesheet: &str) -> (Document, StyledNode) {
+        let document = Parser::parse(source.to_string());
+        let stylesheet = css::parse(stylesheet.to_string());
+        let root = style_tree(&document, &[&stylesheet], &Default::default());
+        (document, root)
+    }
+
+    fn collect_text(node: &StyledNode, texts: &mut Vec<String>) {
+        texts.extend(node.text.clone());
+        for child in &node.children {
+            collect_text(child, texts);
+        }
+    }
+
+    /// The `id` of each element in `source` that `selectors` match, in document order.
+    fn select(source: &str, selectors: &str) -> Vec<String> {
+        let document = Parser::parse(source.to_string());
+        let stylesheet = css::parse(format!("{} {{}}", selectors));
+        assert_eq!(stylesheet.rules.len(), 1, "invalid selector {}", selectors);
+        let selectors = &stylesheet.rules[0].selectors;
+        document.descendant_elements(document.root())
+            .filter(|&(id, _)| selectors.iter().any(|selector| matches(&document, id, selector)))
+            .filter_map(|(_, elem)| elem.id().map(|id| id.to_string()))
+            .collect()
+    }
+
+    /// The declarations cascaded onto the element with the `id` `target` from `stylesheets`,
+    /// with each property's value written back out as CSS.
+    fn cascade(source: &str, stylesheets: &[(Origin, &str)], target: &str) -> Vec<String> {
+        let document = Parser::parse(source.to_string());
+        let stylesheets: Vec<(Origin, StyleSheet)> = stylesheets.iter()
+            .map(|&(origin, source)| (origin, css::parse(source.to_string())))
+            .collect();
+        let stylesheets: Vec<(Origin, &StyleSheet)> =
+            stylesheets.iter().map(|&(origin, ref stylesheet)| (origin, stylesheet)).collect();
+        let id = document.get_element_by_id(target).unwrap();
+        let mut values: Vec<String> = specified_values(&document, id, None, &stylesheets)
+            .into_iter()
+            .map(|(name, value)| match value {
+                Value::Keyword(keyword) => format!("{}: {}", name, keyword),
+                value => format!("{}: {:?}", name, value),
+            })
+            .collect();
+        values.sort();
+        values
+    }
+
+    /// The computed value of `name` on the element with the `id` `target`.
+    fn computed(source: &str, stylesheet: &str, target: &str, name: &str) -> Option<Value> {
+        let (document, root) = style(source, stylesheet);
+        let id = document.get_element_by_id(target).unwrap();
+        find(&root, id).computed_value(name)
+    }
+
+    /// The styled node for the element `id`.
+    fn find(node: &StyledNode, id: NodeId) -> &StyledNode {
+        fn search(node: &StyledNode, id: NodeId) -> Option<&StyledNode> {
+            if node.node == id && node.pseudo_element.is_none() {
+                return Some(node);
             }
+            node.children.iter().filter_map(|child| search(child, id)).next()
+        }
+        search(node, id).expect("element is in the style tree")
+    }
+
+    fn value(source: &str) -> Option<Value> {
+        css::parse_declarations(format!("x: {}", source)).into_iter().next().map(|d| d.value)
+    }
+
+    fn collect_generated(node: &StyledNode, generated: &mut Vec<String>) {
+        if node.pseudo_element.is_some() {
+            generated.extend(node.children.iter().filter_map(|child| child.text.clone()));
         }
+        for child in &node.children {
+            collect_generated(child, generated);
+        }
+    }
+
+    /// The content generated for every `::before` and `::after` pseudo-element, in order.
+    fn generated(source: &str, stylesheet: &str) -> Vec<String> {
+        let (_, root) = style(source, stylesheet);
+        let mut generated = Vec::new();
+        collect_generated(&root, &mut generated);
+        generated
+    }
+
+    /// The text of every text node in the body after white space processing.
+    fn texts(source: &str, stylesheet: &str) -> Vec<String> {
+        let (_, root) = style(source, stylesheet);
+        let mut texts = Vec::new();
+        collect_text(&root, &mut texts);
+        texts
+    }
+
+    #[test]
+    fn white_space_collapses_across_elements() {
+        assert_eq!(texts("<p> a  <b> b </b>\n c </p>", ""), ["a ", "b ", "c"]);
+        assert_eq!(texts("<p>a<b> </b> <i> b</i></p>", ""), ["a", " ", "", "b"]);
+        // Blocks start runs of their own.
+        assert_eq!(texts("<div>a <p> b </p> c</div>", ""), ["a", "b", "c"]);
+        assert_eq!(texts("<p>\t a \t</p>", "p { white-space: nowrap; }"), ["a"]);
+    }
+
+    #[test]
+    fn preserved_white_space() {
+        assert_eq!(texts("<pre> a  <b> b </b></pre>", ""), [" a  ", " b "]);
+        assert_eq!(texts("<p> a  <b> b </b> c</p>", "b { white-space: pre-wrap; }"),
+                   ["a ", " b ", " c"]);
+    }
+
+    #[test]
+    fn pre_line_keeps_segment_breaks() {
+        assert_eq!(texts("<p> a  \n  b <b> \n c</b>\n</p>", "p { white-space: pre-line; }"),
+                   ["a\nb", "\nc", "\n"]);
+    }
+
+    #[test]
+    fn combinators() {
+        let source = "<div id=a><p id=b><span id=c></span></p>text<span id=d></span>\
+                      <!-- comment --><span id=e></span></div>";
+        assert_eq!(select(source, "div span"), ["c", "d", "e"]);
+        assert_eq!(select(source, "div > span"), ["d", "e"]);
+        // Text and comments between siblings do not count.
+        assert_eq!(select(source, "p + span"), ["d"]);
+        assert_eq!(select(source, "span + span"), ["e"]);
+        assert_eq!(select(source, "p ~ span"), ["d", "e"]);
+        assert_eq!(select(source, "div > p > span"), ["c"]);
+        assert_eq!(select(source, "body > p span"), Vec::<String>::new());
+        assert_eq!(select(source, "#a span ~ span"), ["e"]);
+        assert_eq!(select(source, "html div p ~ *"), ["d", "e"]);
+        assert_eq!(select(source, "div>p+span~span"), ["e"]);
+    }
+
+    #[test]
+    fn attribute_selectors() {
+        let source = "<p id=a lang=en-US class='x y' data-v=abc title=Foo></p>\
+                      <p id=b lang=en data-v='' title=foo></p>\
+                      <input id=c type=TEXT><p id=d lang=english>";
+        assert_eq!(select(source, "[title]"), ["a", "b"]);
+        assert_eq!(select(source, "[TITLE]"), ["a", "b"]);
+        assert_eq!(select(source, "[title=foo]"), ["b"]);
+        assert_eq!(select(source, "[class~=y]"), ["a"]);
+        assert_eq!(select(source, "[class~='x y']"), Vec::<String>::new());
+        assert_eq!(select(source, "[class~='']"), Vec::<String>::new());
+        assert_eq!(select(source, "[lang|=en]"), ["a", "b"]);
+        assert_eq!(select(source, "[data-v^=ab]"), ["a"]);
+        assert_eq!(select(source, "[data-v$=bc]"), ["a"]);
+        assert_eq!(select(source, "[data-v*=b]"), ["a"]);
+        // An empty value never matches the prefix, suffix and substring operators.
+        assert_eq!(select(source, "[data-v^=''], [data-v$=''], [data-v*='']"),
+                   Vec::<String>::new());
+        assert_eq!(select(source, "[data-v='']"), ["b"]);
+    }
+
+    #[test]
+    fn attribute_selector_case_sensitivity() {
+        let source = "<p id=a lang=en-US title=Foo></p><input id=b type=TEXT>\
+                      <svg><a id=c type=TEXT></a></svg>";
+        assert_eq!(select(source, "[title=foo i]"), ["a"]);
+        assert_eq!(select(source, "[title=FOO I]"), ["a"]);
+        assert_eq!(select(source, "[title=Foo s]"), ["a"]);
+        // Some HTML attributes are compared without case unless the `s` flag says otherwise,
+        // but not on foreign elements.
+        assert_eq!(select(source, "[lang|=EN]"), ["a"]);
+        assert_eq!(select(source, "[type=text]"), ["b"]);
+        assert_eq!(select(source, "[type=text s]"), Vec::<String>::new());
+        assert_eq!(select(source, "[type=text i]"), ["b", "c"]);
+    }
+
+    /// The specificity of a selector.
+    fn specificity(selector: &str) -> Specificity {
+        let stylesheet = css::parse(format!("{} {{}}", selector));
+        stylesheet.rules[0].selectors[0].specificity()
+    }
+
+    #[test]
+    fn child_position_pseudo_classes() {
+        let source = "<ul id=l>text<li id=a class=x></li><li id=b></li><!-- c -->\
+                      <li id=c class=x></li><li id=d></li><li id=e class=x></li></ul>";
+        assert_eq!(select(source, "li:first-child"), ["a"]);
+        assert_eq!(select(source, "li:last-child"), ["e"]);
+        assert_eq!(select(source, "li:only-child, ul:only-child"), ["l"]);
+        assert_eq!(select(source, "li:nth-child(2n+1)"), ["a", "c", "e"]);
+        assert_eq!(select(source, "li:nth-child(even)"), ["b", "d"]);
+        assert_eq!(select(source, "li:nth-child(-n+2)"), ["a", "b"]);
+        assert_eq!(select(source, "li:nth-child(3)"), ["c"]);
+        assert_eq!(select(source, "li:nth-child(0n+0)"), Vec::<String>::new());
+        assert_eq!(select(source, "li:nth-last-child(2)"), ["d"]);
+        assert_eq!(select(source, "li:nth-child(2 of .x)"), ["c"]);
+        assert_eq!(select(source, "li:nth-last-child(odd of .x)"), ["a", "e"]);
+    }
+
+    #[test]
+    fn type_pseudo_classes() {
+        let source = "<div id=p><p id=a></p><span id=b></span><p id=c></p><i id=d></i>\
+                      <p id=e></p></div>";
+        assert_eq!(select(source, "div > :first-of-type"), ["a", "b", "d"]);
+        assert_eq!(select(source, "div > :last-of-type"), ["b", "d", "e"]);
+        assert_eq!(select(source, "div > :only-of-type"), ["b", "d"]);
+        assert_eq!(select(source, "p:nth-of-type(2)"), ["c"]);
+        assert_eq!(select(source, "p:nth-last-of-type(n+2)"), ["a", "c"]);
+    }
+
+    #[test]
+    fn root_and_empty() {
+        let source = "<div id=a></div><div id=b><!-- c --></div><div id=c> </div>\
+                      <div id=d><i id=e></i></div>";
+        assert_eq!(select(source, ":root").len(), 0);
+        assert_eq!(select("<html id=h><p id=p>", ":root"), ["h"]);
+        assert_eq!(select(source, "div:empty"), ["a", "b"]);
+    }
+
+    #[test]
+    fn logical_pseudo_classes() {
+        let source = "<div id=a class=x><p id=b></p></div><div id=c><p id=d class=y></p>\
+                      <span id=e></span></div><div id=f></div><p id=g></p>";
+        assert_eq!(select(source, "div:not(.x, :has(p))"), ["f"]);
+        assert_eq!(select(source, ":is(div, span):not(#a)"), ["c", "e", "f"]);
+        assert_eq!(select(source, ":where(.x, .y)"), ["a", "d"]);
+        assert_eq!(select(source, ":is(.x p, #c > span)"), ["b", "e"]);
+        assert_eq!(select(source, "div:has(.y)"), ["c"]);
+        assert_eq!(select(source, "div:has(> p + span)"), ["c"]);
+        assert_eq!(select(source, "div:has(> span:first-child)"), Vec::<String>::new());
+        assert_eq!(select(source, "div:has(+ div), p:has(~ span)"), ["a", "c", "d"]);
+        assert_eq!(select(source, "div:has(+ p)"), ["f"]);
+        // The relative selector is anchored at the element with `:has`, not at any ancestor.
+        assert_eq!(select(source, "p:has(> p)"), Vec::<String>::new());
+        assert_eq!(select(source, "body :has(div > p)"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn pseudo_class_specificity() {
+        assert_eq!(specificity("li:first-child"), (0, 1, 1));
+        assert_eq!(specificity(":is(#a, .b) p"), (1, 0, 1));
+        assert_eq!(specificity(":not(.a, p)"), (0, 1, 0));
+        assert_eq!(specificity(":where(#a, .b) p"), (0, 0, 1));
+        assert_eq!(specificity(":has(> #a, .b)"), (1, 0, 0));
+        assert_eq!(specificity(":nth-child(2n)"), (0, 1, 0));
+        assert_eq!(specificity(":nth-child(2n of #a, p)"), (1, 1, 0));
+        assert_eq!(specificity("p::before"), (0, 0, 2));
+    }
+
+    #[test]
+    fn nth_matches_extreme_values() {
+        let nth = |a, b| css::Nth { a, b };
+        assert!(nth(2, 1).matches(5) && !nth(2, 1).matches(4));
+        assert!(nth(-1, 3).matches(3) && nth(-1, 3).matches(1) && !nth(-1, 3).matches(4));
+        assert!(nth(i32::MAX, i32::MIN).matches(i32::MIN + i32::MAX));
+        assert!(!nth(i32::MIN, i32::MAX).matches(1));
+        assert!(nth(0, i32::MAX).matches(i32::MAX));
+    }
+
+    #[test]
+    fn sibling_lists_are_numbered_independently() {
+        let stylesheet = "li::before { content: counters(list-item, '.'); }";
+        assert_eq!(generated("<ol><li>a<li>b</ol><ol><li>c</ol><ul><li>d</ul>", stylesheet),
+                   ["1", "2", "1", "1"]);
+        // Each nested list nests a new instance inside its list item's.
+        assert_eq!(generated("<ol><li>a<ol><li>b<li>c</ol><li>d<ol><li>e</ol></ol>", stylesheet),
+                   ["1", "1.1", "1.2", "2", "2.1"]);
+        assert_eq!(generated("<ol><li><ol><li>a</ol><ol><li>b<li>c</ol></ol>", stylesheet),
+                   ["1", "1.1", "1.1", "1.2"]);
+    }
+
+    #[test]
+    fn counter_reset_replaces_instances_of_the_same_level() {
+        let stylesheet = "p { counter-reset: c 1 c 5; } div { counter-reset: c 10; } \
+                          i { counter-increment: c; } i::after { content: counters(c, '-'); }";
+        // A counter stays in scope for the following siblings of the element that reset it.
+        assert_eq!(generated("<p><i></i></p><i></i>", stylesheet), ["6", "7"]);
+        assert_eq!(generated("<div><p><i></i></p><i></i></div>", stylesheet), ["10-6", "10-7"]);
+        assert_eq!(generated("<div></div><p></p><i></i><div><i></i></div>", stylesheet),
+                   ["6", "11"]);
+    }
+
+    #[test]
+    fn elements_without_boxes_leave_counters_alone() {
+        let stylesheet = "li::before { content: counter(list-item) '.'; } \
+                          .hidden { display: none; }";
+        assert_eq!(generated("<ol><li>a<li class=hidden>b<li>c</ol>", stylesheet),
+                   ["1.", "2."]);
+        let source = "<ol><li>a<section class=hidden><li>b<ol><li>c</ol></section><li>d</ol>";
+        assert_eq!(generated(source, stylesheet), ["1.", "2."]);
+        let stylesheet = "body { counter-reset: p; } p { counter-increment: p; } \
+                          p::after { content: counter(p); } .hidden { display: none; }";
+        assert_eq!(generated("<p>a</p><p class=hidden>b</p><p>c</p>", stylesheet), ["1", "2"]);
+    }
+
+    #[test]
+    fn cascade_by_specificity_and_order() {
+        let source = "<p id=a class=x>";
+        let author = |stylesheet| cascade(source, &[(Origin::Author, stylesheet)], "a");
+        assert_eq!(author("#a { color: red; } .x { color: blue; } p { color: green; }"),
+                   ["color: red"]);
+        assert_eq!(author("p.x { color: red; } .x { color: blue; }"), ["color: red"]);
+        // Later rules win ties, within a rule and across rules.
+        assert_eq!(author(".x { color: red; color: blue; } p { color: green; }"),
+                   ["color: blue"]);
+        assert_eq!(author(".x { color: red; } .x { color: blue; }"), ["color: blue"]);
+        // The most specific matching selector of a rule counts.
+        assert_eq!(author("#a, p { color: red; } .x { color: blue; }"), ["color: red"]);
+        assert_eq!(author(":where(#a) { color: red; } p { color: blue; }"), ["color: blue"]);
+    }
+
+    #[test]
+    fn cascade_by_origin_and_importance() {
+        let source = "<p id=a>";
+        let ua = (Origin::UserAgent, "#a { color: red; font-style: italic !important; }");
+        let user = (Origin::User, "p { color: green; font-style: normal; }");
+        let author = (Origin::Author, "p { color: blue; font-style: oblique; }");
+        assert_eq!(cascade(source, &[ua, user, author], "a"),
+                   ["color: blue", "font-style: italic"]);
+        assert_eq!(cascade(source, &[author, user, ua], "a"),
+                   ["color: blue", "font-style: italic"]);
+        assert_eq!(cascade(source, &[ua, user], "a"), ["color: green", "font-style: italic"]);
+        let user = (Origin::User, "p { color: green !important; }");
+        let author = (Origin::Author, "#a { color: blue !important; }");
+        assert_eq!(cascade(source, &[ua, user, author], "a")[0], "color: green");
+        let ua = (Origin::UserAgent, "p { color: red !important; }");
+        assert_eq!(cascade(source, &[ua, user, author], "a")[0], "color: red");
+    }
+
+    #[test]
+    fn cascade_style_attribute() {
+        let source = "<p id=a style='color: red; font-style: italic'>";
+        let author = |stylesheet| cascade(source, &[(Origin::Author, stylesheet)], "a");
+        assert_eq!(author("#a#a { color: blue; }"), ["color: red", "font-style: italic"]);
+        assert_eq!(author("p { color: blue !important; }"),
+                   ["color: blue", "font-style: italic"]);
+        let source = "<p id=a style='color: red !important'>";
+        assert_eq!(cascade(source, &[(Origin::Author, "p { color: blue !important; }")], "a"),
+                   ["color: red"]);
+        assert_eq!(cascade(source, &[(Origin::User, "p { color: blue !important; }")], "a"),
+                   ["color: blue"]);
+    }
+
+    #[test]
+    fn inherited_properties_flow_to_children() {
+        let source = "<div id=a><p id=b><span id=c></span></p></div>";
+        let stylesheet = "#a { color: red; font-size: 20px; white-space: pre; margin-left: 5px; }";
+        let computed = |target, name| computed(source, stylesheet, target, name);
+        assert_eq!(computed("c", "color"), value("red"));
+        assert_eq!(computed("c", "font-size"), value("20px"));
+        assert_eq!(computed("c", "white-space"), value("pre"));
+        assert_eq!(computed("a", "margin-left"), value("5px"));
+        assert_eq!(computed("b", "margin-left"), value("0"));
+        assert_eq!(computed("c", "display"), value("inline"));
+        assert_eq!(computed("b", "display"), value("block"));
+    }
+
+    #[test]
+    fn explicit_defaulting_keywords() {
+        let source = "<div id=a><p id=b></p></div>";
+        let computed = |rule: &str, name| {
+            let stylesheet = format!("#a {{ color: red; margin-left: 5px; }} #b {{ {} }}", rule);
+            computed(source, &stylesheet, "b", name)
+        };
+        assert_eq!(computed("margin-left: inherit", "margin-left"), value("5px"));
+        assert_eq!(computed("color: initial", "color"), value("#000000"));
+        assert_eq!(computed("color: unset", "color"), value("red"));
+        assert_eq!(computed("margin-left: unset", "margin-left"), value("0"));
+        // The user-agent stylesheet's value for display is replaced by the initial one.
+        assert_eq!(computed("display: initial", "display"), value("inline"));
+        assert_eq!(computed("display: unset", "display"), value("inline"));
+    }
+
+    #[test]
+    fn currentcolor_resolves_to_the_color() {
+        let source = "<div id=a><p id=b></p></div>";
+        let computed = |stylesheet, name| computed(source, stylesheet, "b", name);
+        assert_eq!(computed("#b { color: red; }", "border-color"), value("red"));
+        assert_eq!(computed("#b { color: red; border-color: currentcolor; }", "border-color"),
+                   value("red"));
+        assert_eq!(computed("#a { color: red; } #b { background: currentcolor; }", "background"),
+                   value("red"));
+        assert_eq!(computed("#a { color: red; } #b { color: currentcolor; }", "color"),
+                   value("red"));
+        assert_eq!(computed("", "border-color"), value("#000000"));
+        assert_eq!(computed("", "background"), value("transparent"));
+        assert_eq!(computed("", "font-size"), value("16px"));
+    }
+
+    #[test]
+    fn user_agent_stylesheet_has_no_errors() {
+        let (_, warnings) = css::parse_with_warnings(include_str!("user_agent.css").to_string());
+        assert_eq!(warnings, []);
+    }
+
+    #[test]
+    fn user_agent_stylesheet_option() {
+        let document = Parser::parse("<div id=a><p id=b></p></div>".to_string());
+        let head = document.get_elements_by_tag_name(document.root(), "head")[0];
+        let div = document.get_element_by_id("a").unwrap();
+        let p = document.get_element_by_id("b").unwrap();
+        let author = css::parse("p { margin-top: 1px; } div { margin-left: 4px; }".to_string());
+        let style = |user_agent_stylesheet| {
+            style_tree(&document, &[&author], &StyleOptions { user_agent_stylesheet })
+        };
+        let computed = |root: &StyledNode, id, name| find(root, id).computed_value(name);
+
+        let root = style(Some(user_agent_stylesheet()));
+        assert_eq!(computed(&root, head, "display"), value("none"));
+        assert_eq!(computed(&root, div, "display"), value("block"));
+        assert_eq!(computed(&root, p, "margin-bottom"), value("16px"));
+
+        let root = style(None);
+        assert_eq!(computed(&root, head, "display"), value("inline"));
+        assert_eq!(computed(&root, div, "display"), value("inline"));
+        assert_eq!(computed(&root, p, "margin-bottom"), value("0"));
+        assert_eq!(computed(&root, div, "margin-left"), value("4px"));
+
+        // A replacement is applied at the user-agent origin, although it was parsed as an
+        // author stylesheet.
+        let replacement = css::parse("head, div { display: block; } p { margin: 2px; } \
+                                      div { margin-left: 3px !important; }".to_string());
+        let root = style(Some(&replacement));
+        assert_eq!(computed(&root, head, "display"), value("block"));
+        assert_eq!(computed(&root, div, "display"), value("block"));
+        assert_eq!(computed(&root, p, "display"), value("inline"));
+        assert_eq!(computed(&root, p, "margin-top"), value("1px"));
+        assert_eq!(computed(&root, p, "margin-bottom"), value("2px"));
+        assert_eq!(computed(&root, div, "margin-left"), value("3px"));
     }
 }